@@ -1,15 +1,276 @@
 use anyhow::Result;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use dns_geolocation_checker::{
-    configs_parser::ConfigParser,
-    ip_geo_checker::{IpGeoChecker, IpGeoCheckerTestedData},
-    ip_geo_client::IpGeoProviderType,
+    configs_parser::{Config, ConfigFormat, ConfigParser},
+    dns_client::{DnsRecordType, DnsResolver},
+    error::Error,
+    ip_geo_checker::{
+        GeoIpResponse, IpGeoCheckerDivergence, IpGeoCheckerResolverSummary, IpGeoCheckerSummary,
+        IpGeoCheckerTestedData,
+    },
+    junit_report::write_junit_report,
+};
+use serde::Serialize;
+use std::{fs::File, io::BufWriter, net::IpAddr, path::Path, process::ExitCode};
+
+#[cfg(any(feature = "ip-api", feature = "mmdb"))]
+use dns_geolocation_checker::{
+    ip_geo_checker::IpGeoChecker,
+    ip_geo_client::{GetGeoIpInfo, IpGeoProviderType},
 };
-use std::env;
 
 #[cfg(feature = "ip-api")]
 use dns_geolocation_checker::ip_geo_client::ip_api_client::IpApiClient;
 #[cfg(feature = "mmdb")]
 use dns_geolocation_checker::ip_geo_client::mmdb_client::MMDBClient;
+#[cfg(feature = "ip-api")]
+use dns_geolocation_checker::ip_geo_client::NewProvider;
+
+/// Check that geo-routed DNS records resolve to the expected countries
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// The path to the config file
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "CONFIG_PATH",
+        default_value = "./configs/config.toml"
+    )]
+    config: String,
+    /// The format of the config file, toml, yaml or json. Defaults to the format matching the
+    /// extension of the file, or toml
+    #[arg(long, global = true)]
+    config_format: Option<ConfigFormat>,
+    /// The IP geo provider, overrides `ip_geo_provider` in the config
+    #[arg(short, long, global = true)]
+    provider: Option<String>,
+    /// The DNS resolver: google, cloudflare, quad9, an IP address with an optional port, any of
+    /// these prefixed with tcp:// to only query over TCP, or with the matching features
+    /// tls://<ip or host>[:port][#server name] or a DNS-over-HTTPS URL. Overrides `dns_resolver`
+    /// and `dns_resolvers` in the config, defaults to google. Repeat to compare several resolvers
+    #[arg(short, long, global = true)]
+    resolver: Vec<DnsResolver>,
+    /// The path to a PEM file of the CA certificates trusted by the tls:// resolvers instead of
+    /// the public web roots
+    #[arg(long, global = true)]
+    ca_cert: Option<String>,
+    /// The path to the MMDB file, overrides `mmdb_path` in the config
+    #[arg(long, global = true)]
+    mmdb_path: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check every domain in the config (default)
+    Check(CheckArgs),
+    /// Resolve a host with an EDNS client subnet
+    Resolve {
+        /// The host to resolve
+        host: String,
+        /// The client subnet, e.g. 175.41.192.0/18
+        #[arg(short, long)]
+        subnet: String,
+        /// The record type to query, A or AAAA
+        #[arg(short = 't', long, default_value = "A")]
+        record_type: DnsRecordType,
+    },
+    /// Look up the geolocation of an IP with the IP geo provider
+    Lookup {
+        /// The IP to look up
+        ip: IpAddr,
+    },
+    /// Validate the config file
+    Validate,
+}
+
+#[derive(Args, Debug, Default)]
+struct CheckArgs {
+    /// The output format
+    #[arg(short, long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// Also write a JUnit XML report to this path
+    #[arg(long)]
+    junit: Option<String>,
+    /// The number of geo mismatches tolerated before the run is considered failed
+    #[arg(long, default_value_t = 0)]
+    max_mismatches: usize,
+}
+
+/// The exit codes of the binary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Every check passed, or the mismatches are within the tolerated threshold
+    Pass = 0,
+    /// More geo mismatches than tolerated, takes precedence over `Error`
+    Mismatch = 1,
+    /// A DNS query or a provider lookup failed
+    Error = 2,
+    /// The config file or the command-line arguments are invalid
+    ConfigError = 3,
+}
+
+impl Outcome {
+    /// Get the outcome of a run from its summary
+    fn from_summary(summary: &IpGeoCheckerSummary, max_mismatches: usize) -> Self {
+        if summary.mismatched > max_mismatches {
+            Outcome::Mismatch
+        } else if summary.errors > 0 {
+            Outcome::Error
+        } else {
+            Outcome::Pass
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome as u8)
+    }
+}
+
+/// An error in the config file or the command-line arguments
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct ConfigError(String);
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum OutputFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// A single JSON document with the summary and every result
+    Json,
+    /// One JSON result per line
+    Ndjson,
+}
+
+/// The JSON document printed by `check --format json`
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    summary: IpGeoCheckerSummary,
+    resolvers: Vec<IpGeoCheckerResolverSummary>,
+    divergences: Vec<IpGeoCheckerDivergence>,
+    results: &'a [IpGeoCheckerTestedData],
+}
+
+impl Cli {
+    /// Load the config file, apply the command-line overrides and validate the result
+    ///
+    /// When the config file is not `required` and does not exist, the overrides are applied to
+    /// an empty config instead. Every problem of an invalid config is printed before failing.
+    fn load_config(&self, required: bool) -> Result<Config> {
+        let exists = Path::new(&self.config).exists();
+        if required && !exists {
+            return Err(ConfigError(format!("config file {} not found", self.config)).into());
+        }
+
+        let mut config = if exists {
+            let format = self
+                .config_format
+                .unwrap_or(ConfigFormat::from_path(&self.config));
+            ConfigParser::try_load_file_as(&self.config, format)
+                .map_err(|e| self.config_error(e))?
+        } else {
+            Config::default()
+        };
+        if let Some(provider) = &self.provider {
+            config.ip_geo_provider = provider.parse().map_err(|e| self.config_error(e))?;
+        }
+        if let Some(mmdb_path) = &self.mmdb_path {
+            config.mmdb_path = Some(mmdb_path.clone());
+        }
+        if !self.resolver.is_empty() {
+            config.dns_resolver = None;
+            config.dns_resolvers = self.resolver.clone();
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            if !Path::new(ca_cert).is_file() {
+                return Err(ConfigError(format!("CA certificate {} not found", ca_cert)).into());
+            }
+            config.dns_resolver = config.dns_resolver.map(|r| self.with_ca_cert(r));
+            config.dns_resolvers = config
+                .dns_resolvers
+                .into_iter()
+                .map(|r| self.with_ca_cert(r))
+                .collect();
+        }
+
+        config.validate().map_err(|e| self.config_error(e))?;
+        Ok(config)
+    }
+
+    /// Trust the `--ca-cert` CA certificates for a DNS-over-TLS resolver
+    fn with_ca_cert(&self, resolver: DnsResolver) -> DnsResolver {
+        match (resolver, &self.ca_cert) {
+            #[cfg(feature = "dns-over-tls")]
+            (DnsResolver::Tls(server), Some(ca_cert)) => {
+                DnsResolver::Tls(server.with_ca_cert(ca_cert))
+            }
+            (resolver, _) => resolver,
+        }
+    }
+
+    /// Convert an error loading the config into a config error, printing every problem of an
+    /// invalid config first
+    fn config_error(&self, e: Error) -> ConfigError {
+        match e {
+            Error::InvalidConfig(problems) => {
+                problems
+                    .iter()
+                    .for_each(|problem| eprintln!("[Invalid] {}", problem));
+                ConfigError(format!(
+                    "{} problem(s) found in {}",
+                    problems.len(),
+                    self.config
+                ))
+            }
+            e => ConfigError(e.to_string()),
+        }
+    }
+}
+
+/// Format the CNAME chain of a result, if any
+fn format_cname_chain(r: &IpGeoCheckerTestedData) -> String {
+    if r.cname_chain.is_empty() {
+        return "".to_string();
+    }
+    format!(", cname: {}", r.cname_chain.join(" -> "))
+}
+
+/// Format the client subnet prefix lengths echoed by the server, if any
+fn format_ecs_scope(r: &IpGeoCheckerTestedData) -> String {
+    match (r.source_prefix, r.scope_prefix) {
+        (Some(source), Some(scope)) => format!(", ecs: /{} scope /{}", source, scope),
+        _ => "".to_string(),
+    }
+}
+
+/// Format the error of a mismatched result other than a geo mismatch, e.g. a too broad scope
+fn format_failed_assertion(r: &IpGeoCheckerTestedData) -> String {
+    match &r.error {
+        Some(e @ (Error::ScopeTooBroad { .. } | Error::TtlTooLong { .. })) => {
+            format!(", error: {}", e)
+        }
+        _ => "".to_string(),
+    }
+}
+
+/// Format the IP of a result, `-` for results without one, e.g. an expected NXDOMAIN
+fn format_ip(r: &IpGeoCheckerTestedData) -> String {
+    r.ip.map_or("-".to_string(), |ip| ip.to_string())
+}
+
+/// Format the authoritative nameserver of a result, if any
+fn format_nameserver(r: &IpGeoCheckerTestedData) -> String {
+    match &r.nameserver {
+        Some(nameserver) => format!(", nameserver: {} ({})", nameserver, r.resolver),
+        None => "".to_string(),
+    }
+}
 
 fn print_tested_data(data: Vec<IpGeoCheckerTestedData>) {
     data.clone()
@@ -17,34 +278,132 @@ fn print_tested_data(data: Vec<IpGeoCheckerTestedData>) {
         .filter(|r| r.is_ok())
         .for_each(|r| {
             println!(
-                "[Matched] {}, ip: {}, subnet: {}, expected: {}, actual: {}",
-                r.host, r.ip, r.subnet, r.expected, r.actual
+                "[Matched] {}, ip: {}, subnet: {}, expected: {}, actual: {}{}{}{}",
+                r.host,
+                format_ip(&r),
+                r.subnet,
+                r.expected.join("/"),
+                r.actual,
+                format_ecs_scope(&r),
+                format_cname_chain(&r),
+                format_nameserver(&r)
+            );
+        });
+
+    data.clone()
+        .into_iter()
+        .filter(|r: &IpGeoCheckerTestedData| r.is_mismatch())
+        .for_each(|r| {
+            eprintln!(
+                "[Mismatched] {}, ip: {}, subnet: {}, expected: {}, actual: {}{}{}{}{}",
+                r.host,
+                format_ip(&r),
+                r.subnet,
+                r.expected.join("/"),
+                r.actual,
+                format_ecs_scope(&r),
+                format_cname_chain(&r),
+                format_nameserver(&r),
+                format_failed_assertion(&r),
             );
         });
 
     data.clone()
         .into_iter()
-        .filter(|r: &IpGeoCheckerTestedData| r.is_err())
+        .filter(|r| r.is_inconclusive())
         .for_each(|r| {
             eprintln!(
-                "[Mismatched] {}, ip: {}, subnet: {}, expected: {}, actual: {}, error: {:?}",
+                "[Inconclusive] {}, ip: {}, subnet: {}, expected: {}, actual: {}{}{}, reason: {}",
                 r.host,
-                r.ip,
+                format_ip(&r),
                 r.subnet,
-                r.expected,
+                r.expected.join("/"),
                 r.actual,
-                r.err()
+                format_cname_chain(&r),
+                format_nameserver(&r),
+                r.err().unwrap()
             );
         });
+
+    data.into_iter().filter(|r| r.is_error()).for_each(|r| {
+        eprintln!(
+            "[Error] {}, subnet: {}, expected: {}{}{}, error: {}",
+            r.host,
+            r.subnet,
+            r.expected.join("/"),
+            format_cname_chain(&r),
+            format_nameserver(&r),
+            r.err().unwrap()
+        );
+    });
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let path = env::var("CONFIG_PATH").unwrap_or("./configs/config.toml".to_string());
-    let parser = ConfigParser::new_with_path(path);
-    let config = parser.config();
-    let geo_ip_provider = config.ip_geo_provider.clone();
-    let data = match geo_ip_provider {
+/// Print the summary of every resolver and the test cases they answered differently, when
+/// more than one resolver was used
+fn print_resolver_comparison(data: &[IpGeoCheckerTestedData]) {
+    let summaries = IpGeoCheckerSummary::by_resolver(data);
+    if summaries.len() < 2 {
+        return;
+    }
+
+    summaries.iter().for_each(|s| {
+        println!(
+            "[Resolver] {}, total: {}, matched: {}, mismatched: {}, inconclusive: {}, errors: {}",
+            s.resolver,
+            s.summary.total,
+            s.summary.matched,
+            s.summary.mismatched,
+            s.summary.inconclusive,
+            s.summary.errors
+        );
+    });
+
+    IpGeoCheckerDivergence::from_results(data)
+        .iter()
+        .for_each(|d| {
+            let answers: Vec<String> = d
+                .answers
+                .iter()
+                .map(|a| format!("{}: {}", a.resolver, a.countries.join("/")))
+                .collect();
+            eprintln!(
+                "[Divergent] {}, subnet: {}, expected: {}, {}",
+                d.host,
+                d.subnet,
+                d.geo,
+                answers.join(", ")
+            );
+        });
+}
+
+fn print_json(data: &[IpGeoCheckerTestedData], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let report = JsonReport {
+                summary: IpGeoCheckerSummary::from_results(data),
+                resolvers: IpGeoCheckerSummary::by_resolver(data),
+                divergences: IpGeoCheckerDivergence::from_results(data),
+                results: data,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        _ => {
+            for r in data {
+                println!("{}", serde_json::to_string(r)?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg_attr(
+    not(any(feature = "ip-api", feature = "mmdb")),
+    allow(unreachable_code, unused_variables)
+)]
+async fn check(cli: &Cli, args: &CheckArgs) -> Result<Outcome> {
+    let config = cli.load_config(true)?;
+    let data: Vec<IpGeoCheckerTestedData> = match config.ip_geo_provider {
         #[cfg(feature = "ip-api")]
         IpGeoProviderType::IpApi => {
             IpGeoChecker::<IpApiClient>::new()
@@ -57,16 +416,179 @@ async fn main() -> Result<()> {
         IpGeoProviderType::MMDB => {
             IpGeoChecker::<MMDBClient>::new()
                 .config(&config)
-                .with_mmdb_client()
+                .try_with_mmdb_client()
+                .map_err(|e| cli.config_error(e))?
                 .check()
                 .await
         }
-        _ => panic!(
-            "[Error] Invalid IP Geo Provider. Please add a valid provider in the config file."
-        ),
+        _ => {
+            return Err(ConfigError(
+                "Invalid IP Geo Provider. Please add a valid provider in the config file."
+                    .to_string(),
+            )
+            .into())
+        }
     };
 
-    print_tested_data(data);
+    if let Some(path) = &args.junit {
+        write_junit_report(&data, BufWriter::new(File::create(path)?))?;
+    }
 
-    Ok(())
+    let outcome = Outcome::from_summary(
+        &IpGeoCheckerSummary::from_results(&data),
+        args.max_mismatches,
+    );
+
+    match args.format {
+        OutputFormat::Text => {
+            print_tested_data(data.clone());
+            print_resolver_comparison(&data);
+        }
+        format => print_json(&data, format)?,
+    }
+
+    Ok(outcome)
+}
+
+async fn resolve(
+    cli: &Cli,
+    host: &str,
+    subnet: &str,
+    record_type: DnsRecordType,
+) -> Result<Outcome> {
+    let config = cli.load_config(false)?;
+    let mut resolvers = config.resolvers();
+    if resolvers.is_empty() {
+        resolvers.push(DnsResolver::Google);
+    }
+
+    for resolver in resolvers.iter() {
+        if resolvers.len() > 1 {
+            println!("resolver: {}", resolver);
+        }
+        let client = resolver.try_connect_with_options(config.dns_query).await?;
+        let answer = client
+            .resolve_with_subnet_and_type(host, subnet, record_type)
+            .await?;
+
+        if !answer.cname_chain.is_empty() {
+            println!("cname: {}", answer.cname_chain.join(" -> "));
+        }
+        if answer.ips.is_empty() {
+            println!("no {} records", record_type);
+        }
+        answer.ips.iter().for_each(|ip| match answer.ttl(*ip) {
+            Some(ttl) => println!("{} (ttl {}s)", ip, ttl),
+            None => println!("{}", ip),
+        });
+        if let Some(ecs) = answer.ecs {
+            println!("ecs: /{} scope /{}", ecs.source_prefix, ecs.scope_prefix);
+        }
+        let flags = [
+            ("aa", answer.flags.authoritative),
+            ("ra", answer.flags.recursion_available),
+            ("ad", answer.flags.authentic_data),
+        ]
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(flag, _)| *flag)
+        .collect::<Vec<_>>();
+        println!("rcode: {}, flags: [{}]", answer.rcode, flags.join(" "));
+        if let Some(server) = answer.server {
+            println!("server: {}", server);
+        }
+        println!(
+            "transport: {}, attempts: {}, latency: {:?}, response time: {:?}",
+            answer.transport, answer.attempts, answer.latency, answer.response_time
+        );
+    }
+
+    Ok(Outcome::Pass)
+}
+
+#[cfg_attr(
+    not(any(feature = "ip-api", feature = "mmdb")),
+    allow(unreachable_code, unused_variables)
+)]
+async fn lookup(cli: &Cli, ip: IpAddr) -> Result<Outcome> {
+    let config = cli.load_config(false)?;
+    let geoip: GeoIpResponse = match config.ip_geo_provider {
+        #[cfg(feature = "ip-api")]
+        IpGeoProviderType::IpApi => IpApiClient::new(&config).get_geoip_info(ip).await?,
+        #[cfg(feature = "mmdb")]
+        IpGeoProviderType::MMDB => {
+            MMDBClient::try_new(&config)
+                .map_err(|e| cli.config_error(e))?
+                .get_geoip_info(ip)
+                .await?
+        }
+        _ => return Err(ConfigError("Invalid IP Geo Provider.".to_string()).into()),
+    };
+
+    let field = |value: &Option<String>| value.clone().unwrap_or("-".to_string());
+    println!("ip: {}", geoip.query);
+    println!(
+        "country: {} ({})",
+        field(&geoip.country),
+        field(&geoip.country_code)
+    );
+    println!(
+        "region: {} ({})",
+        field(&geoip.region_name),
+        field(&geoip.region)
+    );
+    println!("city: {}", field(&geoip.city));
+    if let (Some(lat), Some(lon)) = (geoip.lat, geoip.lon) {
+        println!("location: {}, {}", lat, lon);
+    }
+    if let Some(message) = &geoip.message {
+        println!("message: {}", message);
+    }
+
+    Ok(Outcome::Pass)
+}
+
+fn validate(cli: &Cli) -> Result<Outcome> {
+    cli.load_config(true)?;
+    println!("[Ok] {} is valid", cli.config);
+    Ok(Outcome::Pass)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                Outcome::ConfigError.into()
+            } else {
+                Outcome::Pass.into()
+            };
+        }
+    };
+
+    let outcome = match &cli.command {
+        None => check(&cli, &CheckArgs::default()).await,
+        Some(Command::Check(args)) => check(&cli, args).await,
+        Some(Command::Resolve {
+            host,
+            subnet,
+            record_type,
+        }) => resolve(&cli, host, subnet, *record_type).await,
+        Some(Command::Lookup { ip }) => lookup(&cli, *ip).await,
+        Some(Command::Validate) => validate(&cli),
+    };
+
+    match outcome {
+        Ok(outcome) => outcome.into(),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            if e.is::<ConfigError>() {
+                Outcome::ConfigError.into()
+            } else {
+                Outcome::Error.into()
+            }
+        }
+    }
 }
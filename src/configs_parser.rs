@@ -1,31 +1,437 @@
 #![allow(dead_code)]
 
-use serde::Deserialize;
-use std::{collections::HashMap, fs};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
+use crate::dns_client::{
+    parse_name, parse_subnet, DnsOutcome, DnsQueryOptions, DnsRecordType, DnsResolver, DnsTransport,
+};
+use crate::error::{ConfigProblem, Error, Result};
+use crate::interpolation::Interpolated;
 use crate::ip_geo_client::IpGeoProviderType;
 
+#[cfg(feature = "mmdb")]
+use crate::ip_geo_client::mmdb_client::MMDBClient;
+
+/// The default maximum number of test cases resolved at once
+pub const DEFAULT_MAX_CONCURRENT_QUERIES: usize = 64;
+
 /// A struct to hold the parsed config
 #[derive(Default, Debug, Clone, Deserialize)]
 pub struct Config {
-    /// The IP geo provider
+    /// The IP geo provider, `mmdb` or `ip-api`, defaults to the first one compiled in
     #[serde(default)]
     pub ip_geo_provider: IpGeoProviderType,
     #[serde(default)]
     pub mmdb_path: Option<String>,
+    /// The DNS resolver to send the queries to, defaults to Google
+    #[serde(default)]
+    pub dns_resolver: Option<DnsResolver>,
+    /// More DNS resolvers to send the same queries to, to compare their answers
+    #[serde(default)]
+    pub dns_resolvers: Vec<DnsResolver>,
+    /// Named DNS resolvers, e.g. internal split-horizon resolvers, which domains select by name
+    #[serde(default, rename = "resolvers")]
+    pub named_resolvers: HashMap<String, ResolverConfig>,
+    /// The timeouts and retries of the DNS queries
+    #[serde(default)]
+    pub dns_query: DnsQueryOptions,
+    /// Probe every resolver for EDNS client subnet support before checking a domain, the
+    /// results of resolvers which do not support it are inconclusive
+    #[serde(default)]
+    pub probe_ecs: bool,
+    /// The maximum number of test cases resolved at once, defaults to
+    /// [`DEFAULT_MAX_CONCURRENT_QUERIES`]
+    #[serde(default)]
+    pub max_concurrent_queries: Option<usize>,
+    /// Config files to merge the `test_subnets` and `domain` entries of, relative to this file.
+    /// Either a file, a glob pattern such as `domains/*.toml` or a directory such as `conf.d`
+    #[serde(default)]
+    pub include: Vec<String>,
     /// A map of country codes to their respective subnets
+    #[serde(default)]
     pub test_subnets: HashMap<String, RoutingCountryConfig>,
     /// A list of domains and their respective geo routing
+    #[serde(default)]
     pub domain: Vec<DomainConfig>,
 }
 
+impl Config {
+    /// Get the maximum number of test cases resolved at once
+    pub fn max_concurrent_queries(&self) -> usize {
+        self.max_concurrent_queries
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_QUERIES)
+    }
+
+    /// Get every configured DNS resolver, `dns_resolver` first, empty if none is configured
+    pub fn resolvers(&self) -> Vec<DnsResolver> {
+        self.dns_resolver
+            .iter()
+            .chain(self.dns_resolvers.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Get a resolver by name and the options of its queries
+    ///
+    /// The name is looked up in the `resolvers` table first, any other name is parsed as a
+    /// resolver, e.g. `google` or an IP address, queried with the `dns_query` options.
+    pub fn named_resolver(&self, name: &str) -> Result<(DnsResolver, DnsQueryOptions)> {
+        match self.named_resolvers.get(name) {
+            Some(resolver) => Ok((resolver.resolver()?, resolver.options(self.dns_query))),
+            None => name
+                .parse()
+                .map(|resolver| (resolver, self.dns_query))
+                .map_err(|_| Error::UnknownResolver(name.to_string())),
+        }
+    }
+
+    /// Merge the entries of a file included from `source`
+    ///
+    /// A region of `test_subnets` may be defined by several files, but only with the same
+    /// subnets, any conflict is added to `problems`.
+    fn merge(&mut self, source: &str, included: IncludedConfig, problems: &mut Vec<ConfigProblem>) {
+        let mut test_subnets: Vec<_> = included.test_subnets.into_iter().collect();
+        test_subnets.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (region, routing) in test_subnets {
+            match self.test_subnets.get(&region) {
+                Some(defined) if defined.subnets != routing.subnets => {
+                    problems.push(ConfigProblem::new(
+                        format!("{}, test_subnets.{}", source, region),
+                        format!("region {} is already defined with other subnets", region),
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    self.test_subnets.insert(region, routing);
+                }
+            }
+        }
+
+        self.domain
+            .extend(included.domain.into_iter().map(|domain| DomainConfig {
+                source: Some(source.to_string()),
+                ..domain
+            }));
+    }
+
+    /// Check the config for problems before any query is sent
+    ///
+    /// Checks that an IP geo provider is compiled in and that its `mmdb_path`, if set, exists,
+    /// that queries are allowed to run, that every subnet is a valid CIDR, that the hosts are valid DNS names defined in a single
+    /// file, and that the regions and resolvers referenced by the domains are defined. Every
+    /// problem is reported at once with its location, prefixed with the file of the domains
+    /// included from another file.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+
+        match self.ip_geo_provider {
+            IpGeoProviderType::None => problems.push(ConfigProblem::new(
+                "ip_geo_provider",
+                "no IP geo provider is selected, enable the mmdb or ip-api feature",
+            )),
+            #[cfg(feature = "mmdb")]
+            IpGeoProviderType::MMDB => {
+                if let Some(mmdb_path) = MMDBClient::configured_mmdb_path(self) {
+                    if !Path::new(&mmdb_path).is_file() {
+                        problems.push(ConfigProblem::new(
+                            "mmdb_path",
+                            format!("MMDB file {} not found", mmdb_path),
+                        ));
+                    }
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+
+        if self.max_concurrent_queries == Some(0) {
+            problems.push(ConfigProblem::new(
+                "max_concurrent_queries",
+                "at least one query must be allowed at once",
+            ));
+        }
+
+        let mut test_subnets: Vec<_> = self.test_subnets.iter().collect();
+        test_subnets.sort_by_key(|(region, _)| *region);
+        for (region, routing) in test_subnets {
+            for (i, subnet) in routing.subnets.iter().enumerate() {
+                if let Err(e) = parse_subnet(subnet) {
+                    let location = format!("test_subnets.{}.subnets[{}]", region, i);
+                    problems.push(ConfigProblem::new(location, e));
+                }
+            }
+        }
+
+        let mut named_resolvers: Vec<_> = self.named_resolvers.iter().collect();
+        named_resolvers.sort_by_key(|(name, _)| *name);
+        for (name, resolver) in named_resolvers {
+            if let Err(e) = resolver.resolver() {
+                problems.push(ConfigProblem::new(format!("resolvers.{}", name), e));
+            }
+            if let Some(ca_cert) = &resolver.ca_cert {
+                let location = format!("resolvers.{}.ca_cert", name);
+                if resolver.transport != Some(DnsTransport::Tls) {
+                    problems.push(ConfigProblem::new(
+                        location,
+                        "a CA certificate is only used with the tls transport",
+                    ));
+                } else if !Path::new(ca_cert).is_file() {
+                    problems.push(ConfigProblem::new(
+                        location,
+                        format!("CA certificate {} not found", ca_cert),
+                    ));
+                }
+            }
+        }
+
+        // The domains of every file are indexed from 0, as in the file
+        let mut indexes: HashMap<Option<&str>, usize> = HashMap::new();
+        let mut hosts: HashMap<String, (Option<&str>, String)> = HashMap::new();
+        for domain in &self.domain {
+            let index = indexes.entry(domain.source.as_deref()).or_default();
+            let location = match &domain.source {
+                Some(source) => format!("{}, domain[{}]", source, index),
+                None => format!("domain[{}]", index),
+            };
+            *index += 1;
+
+            if domain.host.is_empty() {
+                problems.push(ConfigProblem::new(
+                    format!("{}.host", location),
+                    "the host is empty",
+                ));
+            } else if let Err(e) = parse_name(&domain.host) {
+                problems.push(ConfigProblem::new(format!("{}.host", location), e));
+            } else {
+                // Only the hosts merged from several files conflict, a file may repeat a host
+                let host = domain.host.trim_end_matches('.').to_ascii_lowercase();
+                match hosts.get(&host) {
+                    Some((source, defined)) if *source != domain.source.as_deref() => problems
+                        .push(ConfigProblem::new(
+                            format!("{}.host", location),
+                            format!(
+                                "duplicate host {}, already defined at {}",
+                                domain.host, defined
+                            ),
+                        )),
+                    Some(_) => {}
+                    None => {
+                        hosts.insert(host, (domain.source.as_deref(), location.clone()));
+                    }
+                }
+            }
+
+            for (j, routing) in domain.geo_routing.iter().enumerate() {
+                if !self.test_subnets.contains_key(routing.region()) {
+                    problems.push(ConfigProblem::new(
+                        format!("{}.geo_routing[{}]", location, j),
+                        format!(
+                            "region {} of {} is not defined in test_subnets",
+                            routing.region(),
+                            domain.host
+                        ),
+                    ));
+                }
+                if routing.countries().is_empty() {
+                    problems.push(ConfigProblem::new(
+                        format!("{}.geo_routing[{}].expect", location, j),
+                        format!(
+                            "region {} of {} expects no country",
+                            routing.region(),
+                            domain.host
+                        ),
+                    ));
+                }
+            }
+
+            let mut outcomes: Vec<_> = domain.outcomes.keys().collect();
+            outcomes.sort();
+            for geo in outcomes {
+                if !domain
+                    .geo_routing
+                    .iter()
+                    .any(|routing| routing.region() == geo)
+                {
+                    problems.push(ConfigProblem::new(
+                        format!("{}.outcomes.{}", location, geo),
+                        format!(
+                            "region {} is not in the geo routing of {}",
+                            geo, domain.host
+                        ),
+                    ));
+                }
+            }
+
+            for (j, name) in domain.resolvers.iter().enumerate() {
+                if let Err(e) = self.named_resolver(name) {
+                    problems.push(ConfigProblem::new(
+                        format!("{}.resolvers[{}]", location, j),
+                        e,
+                    ));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(problems))
+        }
+    }
+}
+
+/// A struct to hold a named resolver config
+#[derive(Default, Debug, Clone, Deserialize)]
+pub struct ResolverConfig {
+    /// The address of the resolver, an IP with an optional port, a host with an optional port
+    /// for TLS, or a URL for HTTPS
+    pub address: String,
+    /// The transport the queries are sent over, defaults to UDP retried over TCP when the
+    /// response is truncated
+    #[serde(default)]
+    pub transport: Option<DnsTransport>,
+    /// The time to wait for the response to a single attempt, defaults to `dns_query.timeout_ms`
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// The path to a PEM file of the CA certificates trusted instead of the public web roots,
+    /// for TLS only
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+}
+
+impl ResolverConfig {
+    /// Get the resolver at the address over the transport
+    pub fn resolver(&self) -> Result<DnsResolver> {
+        let address = match self.transport {
+            None | Some(DnsTransport::Udp) => self.address.clone(),
+            Some(DnsTransport::Tcp) => format!("tcp://{}", self.address),
+            Some(DnsTransport::Tls) => format!("tls://{}", self.address),
+            Some(DnsTransport::Https) if self.address.contains("://") => self.address.clone(),
+            Some(DnsTransport::Https) => format!("https://{}", self.address),
+        };
+        let resolver = address
+            .parse()
+            .map_err(|_| Error::InvalidResolver(self.address.clone()))?;
+        Ok(match (resolver, &self.ca_cert) {
+            #[cfg(feature = "dns-over-tls")]
+            (DnsResolver::Tls(server), Some(ca_cert)) => {
+                DnsResolver::Tls(server.with_ca_cert(ca_cert))
+            }
+            (resolver, _) => resolver,
+        })
+    }
+
+    /// Get the options of the queries, overriding the timeout of the default options
+    pub fn options(&self, defaults: DnsQueryOptions) -> DnsQueryOptions {
+        DnsQueryOptions {
+            timeout: self
+                .timeout_ms
+                .map_or(defaults.timeout, Duration::from_millis),
+            ..defaults
+        }
+    }
+}
+
 /// A struct to hold the domain config
 #[derive(Default, Debug, Clone, Deserialize)]
 pub struct DomainConfig {
     /// The host of the domain
     pub host: String,
-    /// A list of country codes to route to
-    pub geo_routing: Vec<String>,
+    /// A list of regions of `test_subnets` to route, with the countries expected to serve them
+    pub geo_routing: Vec<GeoRoutingConfig>,
+    /// The record types to query, defaults to `A` only
+    #[serde(default = "default_record_types")]
+    pub record_types: Vec<DnsRecordType>,
+    /// Send the queries straight to the authoritative nameservers of the domain instead of the
+    /// DNS resolver
+    #[serde(default)]
+    pub authoritative: bool,
+    /// The authoritative nameservers to query, by name or address, implies `authoritative`.
+    /// Defaults to the NS records of the zone of the domain
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// The minimum ECS scope prefix length of the answers, e.g. 24 to require answers specific
+    /// to /24 networks. Answers without the option count as a scope of /0
+    #[serde(default)]
+    pub min_scope_prefix: Option<u8>,
+    /// The maximum TTL of the address records in seconds, e.g. 60 for records which must fail
+    /// over quickly
+    #[serde(default)]
+    pub max_ttl: Option<u32>,
+    /// The names of the resolvers to check the domain against, from the `resolvers` table or
+    /// any resolver such as `google`. Defaults to `dns_resolver` and `dns_resolvers`
+    #[serde(default)]
+    pub resolvers: Vec<String>,
+    /// The geos expected to get an answer without addresses instead of a country, e.g.
+    /// `{ cn = "nxdomain" }` for a service blocked in China
+    #[serde(default)]
+    pub outcomes: HashMap<String, DnsOutcome>,
+    /// The file the domain was included from, `None` for the main config file
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl DomainConfig {
+    /// Get the expected results of a geo, its outcome if it has one, or the countries expected
+    /// to serve it
+    pub fn expected(&self, geo: &str) -> Vec<String> {
+        if let Some(outcome) = self.outcomes.get(geo) {
+            return vec![outcome.to_string()];
+        }
+        self.geo_routing
+            .iter()
+            .find(|routing| routing.region() == geo)
+            .map_or(vec![geo.to_string()], |routing| routing.countries())
+    }
+
+    /// Check if the domain is checked against its authoritative nameservers
+    pub fn is_authoritative(&self) -> bool {
+        self.authoritative || !self.nameservers.is_empty()
+    }
+}
+
+/// A struct to hold a geo routing entry of a domain
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum GeoRoutingConfig {
+    /// A region served from the country with the same code, e.g. `"sg"`
+    Region(String),
+    /// A region served from any of the countries, e.g. `{ region = "my", expect = ["sg"] }`
+    Expect { region: String, expect: Vec<String> },
+}
+
+impl GeoRoutingConfig {
+    /// Get the key of the region in `test_subnets`
+    pub fn region(&self) -> &str {
+        match self {
+            GeoRoutingConfig::Region(region) | GeoRoutingConfig::Expect { region, .. } => region,
+        }
+    }
+
+    /// Get the codes of the countries expected to serve the region
+    pub fn countries(&self) -> Vec<String> {
+        match self {
+            GeoRoutingConfig::Region(region) => vec![region.clone()],
+            GeoRoutingConfig::Expect { expect, .. } => expect.clone(),
+        }
+    }
+}
+
+impl From<&str> for GeoRoutingConfig {
+    fn from(region: &str) -> Self {
+        GeoRoutingConfig::Region(region.to_string())
+    }
+}
+
+fn default_record_types() -> Vec<DnsRecordType> {
+    vec![DnsRecordType::A]
 }
 
 /// A struct to hold the subnets for a country
@@ -35,6 +441,64 @@ pub struct RoutingCountryConfig {
     pub subnets: Vec<String>,
 }
 
+/// The entries of a file included by a config, merged into the config
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludedConfig {
+    /// A map of country codes to their respective subnets
+    #[serde(default)]
+    pub test_subnets: HashMap<String, RoutingCountryConfig>,
+    /// A list of domains and their respective geo routing
+    #[serde(default)]
+    pub domain: Vec<DomainConfig>,
+}
+
+/// The format of a config file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Detect the format of a config file from its extension, `.toml`, `.yaml`, `.yml` or
+    /// `.json`
+    ///
+    /// Files with any other extension, or none, are parsed as TOML.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFormat::Toml => write!(f, "toml"),
+            ConfigFormat::Yaml => write!(f, "yaml"),
+            ConfigFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "toml" => Ok(ConfigFormat::Toml),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "json" => Ok(ConfigFormat::Json),
+            _ => Err(Error::UnknownConfigFormat(s.to_string())),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ConfigParser<T: for<'a> Deserialize<'a>> {
     /// The parsed config
@@ -65,19 +529,277 @@ impl<C: for<'a> Deserialize<'a>> ConfigParser<C> {
     /// assert_eq!(config.domain[0].host, "google.com");
     /// assert_eq!(config.test_subnets.get("us").unwrap().subnets[0], "44.208.193.0/24");
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the contents are invalid, see [`ConfigParser::try_parse`].
     pub fn parse(contents: String) -> C {
-        toml::from_str(&contents).unwrap()
+        Self::try_parse(&contents).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Parse the contents from a TOML string
+    ///
+    /// Fails with the line, column and key of the first error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dns_geolocation_checker::configs_parser::{ConfigParser, Config};
+    /// use dns_geolocation_checker::error::Error;
+    ///
+    /// let test_config = r#"
+    /// [test_subnets]
+    /// us = { subnets = "44.208.193.0/24" }
+    /// "#;
+    ///
+    /// let result = ConfigParser::<Config>::try_parse(test_config);
+    /// let Err(Error::ConfigParse { line, key, .. }) = result else { panic!() };
+    /// assert_eq!(line, Some(3));
+    /// assert_eq!(key.as_deref(), Some("test_subnets.us.subnets"));
+    /// ```
+    pub fn try_parse(contents: &str) -> Result<C> {
+        Self::try_parse_as(contents, ConfigFormat::Toml)
+    }
+
+    /// Parse the contents from a string in the given format
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dns_geolocation_checker::configs_parser::{Config, ConfigFormat, ConfigParser};
+    ///
+    /// let test_config = r#"
+    /// test_subnets:
+    ///   us:
+    ///     subnets: ["44.208.193.0/24"]
+    /// domain:
+    ///   - host: google.com
+    ///     geo_routing: [us]
+    /// "#;
+    ///
+    /// let config: Config = ConfigParser::try_parse_as(test_config, ConfigFormat::Yaml).unwrap();
+    /// assert_eq!(config.domain[0].host, "google.com");
+    /// ```
+    pub fn try_parse_as(contents: &str, format: ConfigFormat) -> Result<C> {
+        parse_str(contents, format, None)
+    }
+
+    /// Read and parse a file, without validating it
+    ///
+    /// The format is detected from the extension of the file, see [`ConfigFormat::from_path`].
+    pub fn try_parse_file<T: ToString>(path: T) -> Result<C> {
+        let path = path.to_string();
+        Self::try_parse_file_as(&path, ConfigFormat::from_path(&path))
+    }
+
+    /// Read and parse a file in the given format, without validating it
+    pub fn try_parse_file_as<T: ToString>(path: T, format: ConfigFormat) -> Result<C> {
+        let path = path.to_string();
+        let contents = fs::read_to_string(&path).map_err(|e| Error::ConfigRead {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        parse_str(&contents, format, Some(&path))
+    }
+}
+
+/// Parse a string in the given format, locating the error in the contents of the file at `path`
+fn parse_str<C: DeserializeOwned>(
+    contents: &str,
+    format: ConfigFormat,
+    path: Option<&str>,
+) -> Result<C> {
+    match format {
+        ConfigFormat::Toml => parse_toml(contents, path),
+        ConfigFormat::Yaml => parse_yaml(contents, path),
+        ConfigFormat::Json => parse_json(contents, path),
+    }
+}
+
+fn parse_toml<C: DeserializeOwned>(contents: &str, path: Option<&str>) -> Result<C> {
+    serde_path_to_error::deserialize(Interpolated(toml::Deserializer::new(contents))).map_err(|e| {
+        let key = e.path().to_string();
+        let e = e.into_inner();
+        let location = e.span().map(|span| line_column(contents, span.start));
+        parse_error(path, &key, location, e.message())
+    })
+}
+
+fn parse_yaml<C: DeserializeOwned>(contents: &str, path: Option<&str>) -> Result<C> {
+    serde_path_to_error::deserialize(Interpolated(serde_yaml::Deserializer::from_str(contents)))
+        .map_err(|e| {
+            let key = e.path().to_string();
+            let e = e.into_inner();
+            let location = e.location().map(|l| (l.line(), l.column()));
+            parse_error(path, &key, location, &e.to_string())
+        })
+}
+
+fn parse_json<C: DeserializeOwned>(contents: &str, path: Option<&str>) -> Result<C> {
+    let json_error = |key: &str, e: serde_json::Error| {
+        let location = Some((e.line(), e.column())).filter(|(line, _)| *line > 0);
+        parse_error(path, key, location, &e.to_string())
+    };
+    let mut de = serde_json::Deserializer::from_str(contents);
+    let config = serde_path_to_error::deserialize(Interpolated(&mut de))
+        .map_err(|e| json_error(&e.path().to_string(), e.into_inner()))?;
+    // Reject trailing characters after the document
+    de.end().map_err(|e| json_error(".", e))?;
+
+    Ok(config)
+}
+
+/// Build the error of a failed parse, the key `.` being the root of the document
+///
+/// YAML errors repeat the key at the start of their message, and YAML and JSON errors the
+/// location at the end, both are only kept in their own fields.
+fn parse_error(
+    path: Option<&str>,
+    key: &str,
+    location: Option<(usize, usize)>,
+    message: &str,
+) -> Error {
+    let message = message
+        .strip_prefix(&format!("{}: ", key))
+        .unwrap_or(message);
+    let message = match location {
+        Some((line, column)) => message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .unwrap_or(message),
+        None => message,
+    };
+    Error::ConfigParse {
+        path: path.map(ToString::to_string),
+        line: location.map(|(line, _)| line),
+        column: location.map(|(_, column)| column),
+        key: Some(key.to_string()).filter(|key| key != "."),
+        message: message.replace('\n', ", "),
+    }
+}
+
+/// Get the line and column of a byte offset, both starting at 1
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Get the files of an `include` entry, relative to the directory of the including file
+///
+/// A directory includes every config file it contains, a pattern the files it matches.
+fn include_files(base: &Path, pattern: &str) -> std::result::Result<Vec<PathBuf>, String> {
+    let path = base.join(pattern);
+    if path.is_dir() {
+        let entries = fs::read_dir(&path).map_err(|e| e.to_string())?;
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.is_file() && is_config_file(file))
+            .collect();
+        files.sort();
+        return Ok(files);
     }
+
+    let pattern = path.to_str().ok_or("the path is not valid UTF-8")?;
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+    let files = glob::glob(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e))?;
+    let mut files: Vec<PathBuf> = files.filter_map(|file| file.ok()).collect();
+    files.sort();
+
+    Ok(files)
+}
+
+/// Check if the extension of a file is the extension of a config format
+fn is_config_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.parse::<ConfigFormat>().is_ok())
+}
+
+/// Get the canonical form of a path, or the path itself if it does not exist
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or(path.to_path_buf())
 }
 
 impl ConfigParser<Config> {
-    /// Create a new ConfigParser with the contents of a file
+    /// Create a new ConfigParser with the contents of a file and the files it includes, without
+    /// validating it
+    ///
+    /// The config must be checked with [`Config::validate`] before any query is sent, or loaded
+    /// with [`ConfigParser::try_new_with_path`], which validates it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a file cannot be read or parsed, see [`ConfigParser::try_load_file_as`].
     pub fn new_with_path<T: ToString>(path: T) -> ConfigParser<Config> {
-        let contents =
-            fs::read_to_string(&path.to_string()).expect("Should have been able to read the file");
+        let path = path.to_string();
+        let config = Self::try_load_file_as(&path, ConfigFormat::from_path(&path))
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        ConfigParser { config }
+    }
+
+    /// Create a new ConfigParser with the contents of a file and the files it includes
+    ///
+    /// Fails if a file cannot be read or parsed, or if the config is invalid, see
+    /// [`Config::validate`].
+    pub fn try_new_with_path<T: ToString>(path: T) -> Result<ConfigParser<Config>> {
+        let path = path.to_string();
+        let config = Self::try_load_file_as(&path, ConfigFormat::from_path(&path))?;
+        config.validate()?;
 
-        ConfigParser {
-            config: ConfigParser::parse(contents),
+        Ok(ConfigParser { config })
+    }
+
+    /// Read and parse a config file in the given format and merge the files it includes,
+    /// without validating it
+    ///
+    /// The included files are read in the order of `include`, the files matching a pattern or
+    /// in a directory in the order of their names, and the format of every included file is
+    /// detected from its extension. Fails with every `include` pattern which is invalid, included
+    /// file which cannot be read or parsed and `test_subnets` region defined with conflicting
+    /// subnets.
+    pub fn try_load_file_as<T: ToString>(path: T, format: ConfigFormat) -> Result<Config> {
+        let path = path.to_string();
+        let mut config: Config = Self::try_parse_file_as(&path, format)?;
+        let mut problems = vec![];
+
+        // A file matched by several patterns, or the config itself, is only read once
+        let mut seen: HashSet<PathBuf> = HashSet::from([canonical_path(Path::new(&path))]);
+        let base = Path::new(&path).parent().unwrap_or(Path::new(""));
+        for (i, pattern) in config.include.clone().into_iter().enumerate() {
+            let files = match include_files(base, &pattern) {
+                Ok(files) => files,
+                Err(message) => {
+                    problems.push(ConfigProblem::new(format!("include[{}]", i), message));
+                    continue;
+                }
+            };
+            for file in files {
+                if !seen.insert(canonical_path(&file)) {
+                    continue;
+                }
+                // Glob matches have no leading `./`, the other files are named the same way
+                let source = file
+                    .strip_prefix(".")
+                    .unwrap_or(&file)
+                    .display()
+                    .to_string();
+                match ConfigParser::<IncludedConfig>::try_parse_file(&source) {
+                    Ok(included) => config.merge(&source, included, &mut problems),
+                    Err(e) => problems.push(ConfigProblem::new(format!("include[{}]", i), e)),
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(Error::InvalidConfig(problems))
         }
     }
 
@@ -91,8 +813,9 @@ impl ConfigParser<Config> {
 mod tests {
     use super::*;
     use std::fs;
-    const TEMP_DIR_PATH: &'static str = "./temp";
-    const TEMP_PATH: &'static str = "./temp/domain.toml";
+    use std::time::Duration;
+    const TEMP_DIR_PATH: &str = "./temp";
+    const TEMP_PATH: &str = "./temp/domain.toml";
 
     fn setup() {
         if fs::read_dir(TEMP_DIR_PATH).is_err() {
@@ -135,6 +858,372 @@ mod tests {
         teardown();
     }
 
+    #[test]
+    fn test_new_with_path_invalid_config() {
+        let dir = "./temp-unvalidated";
+        let path = format!("{}/config.toml", dir);
+        fs::create_dir_all(dir).expect("Unable to create directory");
+        fs::write(
+            &path,
+            "[test_subnets]\nus = { subnets = [\"44.208.193.0/24\"] }\n[[domain]]\nhost = \"google.com\"\ngeo_routing = [\"sg\"]\n",
+        )
+        .expect("Unable to write file");
+        let parser = ConfigParser::new_with_path(&path);
+        let result = ConfigParser::try_new_with_path(&path);
+        fs::remove_dir_all(dir).expect("Unable to remove directory");
+
+        assert_eq!(parser.config().domain[0].host, "google.com");
+        assert!(matches!(result, Err(Error::InvalidConfig(problems)) if problems.len() == 1));
+    }
+
+    #[test]
+    fn test_try_new_with_path_yaml() {
+        let dir = "./temp-yaml";
+        let path = format!("{}/config.yml", dir);
+        fs::create_dir_all(dir).expect("Unable to create directory");
+        fs::write(
+            &path,
+            "test_subnets:\n  us: { subnets: [44.208.193.0/24] }\ndomain:\n  - host: google.com\n    geo_routing: [us]\n",
+        )
+        .expect("Unable to write file");
+        let result = ConfigParser::try_new_with_path(&path);
+        fs::remove_dir_all(dir).expect("Unable to remove directory");
+
+        let parser = result.unwrap();
+        assert_eq!(parser.config().domain[0].host, "google.com");
+        assert_eq!(
+            parser.config().test_subnets["us"].subnets,
+            vec!["44.208.193.0/24"]
+        );
+    }
+
+    #[test]
+    fn test_try_new_with_path_includes() {
+        let dir = "./temp-include";
+        fs::create_dir_all(format!("{}/domains", dir)).expect("Unable to create directory");
+        fs::create_dir_all(format!("{}/conf.d", dir)).expect("Unable to create directory");
+        let write = |file: &str, contents: &str| {
+            fs::write(format!("{}/{}", dir, file), contents).expect("Unable to write file")
+        };
+        write(
+            "config.toml",
+            r#"
+            include = ["domains/*.toml", "conf.d", "*.toml"]
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "www.example.com"
+            geo_routing = ["us"]
+            "#,
+        );
+        write(
+            "domains/b.toml",
+            r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+            sg = { subnets = ["175.41.192.0/18"] }
+
+            [[domain]]
+            host = "b.example.com"
+            geo_routing = ["us", "sg"]
+            "#,
+        );
+        write(
+            "domains/a.toml",
+            "[[domain]]\nhost = \"a.example.com\"\ngeo_routing = [\"us\"]\n",
+        );
+        write("domains/c.yaml", "domain: [{ host: c.example.com }]\n");
+        write(
+            "conf.d/c.json",
+            r#"{ "domain": [{ "host": "c.example.com", "geo_routing": ["sg"] }] }"#,
+        );
+        write("conf.d/README.md", "Domains of the C team");
+        let path = format!("{}/config.toml", dir);
+        let parser = ConfigParser::try_new_with_path(&path).unwrap();
+        let hosts: Vec<(&str, Option<&str>)> = parser
+            .config()
+            .domain
+            .iter()
+            .map(|domain| (domain.host.as_str(), domain.source.as_deref()))
+            .collect();
+        assert_eq!(
+            hosts,
+            vec![
+                ("www.example.com", None),
+                ("a.example.com", Some("temp-include/domains/a.toml")),
+                ("b.example.com", Some("temp-include/domains/b.toml")),
+                ("c.example.com", Some("temp-include/conf.d/c.json")),
+            ]
+        );
+        assert_eq!(parser.config().test_subnets.len(), 2);
+
+        write(
+            "conf.d/d.yaml",
+            "test_subnets:\n  sg: { subnets: [175.41.0.0/16] }\n",
+        );
+        let conflict = ConfigParser::try_new_with_path(&path);
+        write(
+            "conf.d/d.yaml",
+            "domain:\n  - host: WWW.example.com.\n    geo_routing: [sg]\n",
+        );
+        let duplicate = ConfigParser::try_new_with_path(&path);
+        write(
+            "conf.d/d.yaml",
+            "test_subnets:\n  sg: { subnets: [175.41.0.0/16] }\n",
+        );
+        write("conf.d/e.toml", "dns_resolver = \"google\"\n");
+        let unknown = ConfigParser::try_new_with_path(&path);
+        fs::remove_dir_all(dir).expect("Unable to remove directory");
+
+        assert_eq!(
+            conflict.err(),
+            Some(Error::InvalidConfig(vec![ConfigProblem::new(
+                "temp-include/conf.d/d.yaml, test_subnets.sg",
+                "region sg is already defined with other subnets",
+            )]))
+        );
+        assert_eq!(
+            duplicate.err(),
+            Some(Error::InvalidConfig(vec![ConfigProblem::new(
+                "temp-include/conf.d/d.yaml, domain[0].host",
+                "duplicate host WWW.example.com., already defined at domain[0]",
+            )]))
+        );
+        let Some(Error::InvalidConfig(problems)) = unknown.err() else {
+            panic!("the included config should not parse");
+        };
+        let locations: Vec<&str> = problems.iter().map(|p| p.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec!["temp-include/conf.d/d.yaml, test_subnets.sg", "include[1]"]
+        );
+        assert!(problems[1]
+            .message
+            .starts_with("failed to parse temp-include/conf.d/e.toml:1:1, key dns_resolver: "));
+    }
+
+    #[test]
+    fn test_try_new_with_path_errors() {
+        let result = ConfigParser::try_new_with_path("./missing/config.toml");
+        assert!(
+            matches!(result, Err(Error::ConfigRead { path, .. }) if path == "./missing/config.toml")
+        );
+
+        let dir = "./temp-invalid";
+        let path = format!("{}/config.toml", dir);
+        fs::create_dir_all(dir).expect("Unable to create directory");
+        fs::write(
+            &path,
+            "[test_subnets]\nus = { subnets = [\"44.208.193.0/24\"] }\n[[domain]]\n",
+        )
+        .expect("Unable to write file");
+        let result = ConfigParser::try_new_with_path(&path);
+        fs::remove_dir_all(dir).expect("Unable to remove directory");
+
+        let Err(e @ Error::ConfigParse { .. }) = result else {
+            panic!("the config should not parse");
+        };
+        assert_eq!(
+            e.to_string(),
+            "failed to parse ./temp-invalid/config.toml:3:1, key domain[0]: missing field `host`"
+        );
+    }
+
+    #[test]
+    fn test_try_parse_errors() {
+        let test_config = r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+            record_types = ["A", "MX"]
+        "#;
+        assert_eq!(
+            ConfigParser::<Config>::try_parse(test_config).err(),
+            Some(Error::ConfigParse {
+                path: None,
+                line: Some(8),
+                column: Some(34),
+                key: Some("domain[0].record_types[1]".to_string()),
+                message: "unknown variant `MX`, expected one of `A`, `a`, `AAAA`, `aaaa`"
+                    .to_string(),
+            })
+        );
+
+        let result = ConfigParser::<Config>::try_parse("[[domain]\nhost = 1");
+        let Err(Error::ConfigParse {
+            line, column, key, ..
+        }) = result
+        else {
+            panic!("the config should not parse");
+        };
+        assert_eq!((line, column, key), (Some(1), Some(9), None));
+    }
+
+    #[test]
+    fn test_config_format() {
+        assert_eq!(ConfigFormat::from_path("config.toml"), ConfigFormat::Toml);
+        assert_eq!(
+            ConfigFormat::from_path("./configs/config.yaml"),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(ConfigFormat::from_path("config.YML"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("checks.json"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path("config"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("config.conf"), ConfigFormat::Toml);
+        assert_eq!("JSON".parse(), Ok(ConfigFormat::Json));
+        assert_eq!(
+            "ini".parse::<ConfigFormat>(),
+            Err(Error::UnknownConfigFormat("ini".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_yaml_and_json() {
+        let yaml_config = r#"
+            dns_resolver: cloudflare
+            dns_query:
+              timeout_ms: 1000
+            resolvers:
+              corp: { address: 10.0.0.53, transport: tcp }
+            test_subnets:
+              us:
+                subnets: ["44.208.193.0/24"]
+              my:
+                subnets: ["175.136.0.0/16"]
+            domain:
+              - host: google.com
+                geo_routing: [us, { region: my, expect: [sg] }]
+                record_types: [A, AAAA]
+                outcomes: { us: nodata }
+                resolvers: [corp]
+        "#;
+        let json_config = r#"{
+            "dns_resolver": "cloudflare",
+            "dns_query": { "timeout_ms": 1000 },
+            "resolvers": { "corp": { "address": "10.0.0.53", "transport": "tcp" } },
+            "test_subnets": {
+                "us": { "subnets": ["44.208.193.0/24"] },
+                "my": { "subnets": ["175.136.0.0/16"] }
+            },
+            "domain": [{
+                "host": "google.com",
+                "geo_routing": ["us", { "region": "my", "expect": ["sg"] }],
+                "record_types": ["A", "AAAA"],
+                "outcomes": { "us": "nodata" },
+                "resolvers": ["corp"]
+            }]
+        }"#;
+
+        for (contents, format) in [
+            (yaml_config, ConfigFormat::Yaml),
+            (json_config, ConfigFormat::Json),
+        ] {
+            let config: Config = ConfigParser::try_parse_as(contents, format).unwrap();
+            assert_eq!(config.resolvers()[0].to_string(), "cloudflare");
+            assert_eq!(config.dns_query.timeout, Duration::from_millis(1000));
+            assert_eq!(
+                config.named_resolvers["corp"].transport,
+                Some(DnsTransport::Tcp)
+            );
+            assert_eq!(config.test_subnets.len(), 2);
+            let domain = &config.domain[0];
+            assert_eq!(domain.host, "google.com");
+            assert_eq!(
+                domain.record_types,
+                vec![DnsRecordType::A, DnsRecordType::AAAA]
+            );
+            assert_eq!(domain.expected("us"), ["nodata"]);
+            assert_eq!(domain.expected("my"), ["sg"]);
+            assert_eq!(domain.resolvers, vec!["corp"]);
+            assert_eq!(config.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_try_parse_as_errors() {
+        let yaml_config = "test_subnets:\n  us:\n    subnets: 1\n";
+        assert_eq!(
+            ConfigParser::<Config>::try_parse_as(yaml_config, ConfigFormat::Yaml).err(),
+            Some(Error::ConfigParse {
+                path: None,
+                line: Some(3),
+                column: Some(14),
+                key: Some("test_subnets.us.subnets".to_string()),
+                message: "invalid type: integer `1`, expected a sequence".to_string(),
+            })
+        );
+
+        let json_config = "{\"test_subnets\": {},\n \"domain\": [{\"geo_routing\": []}]}";
+        assert_eq!(
+            ConfigParser::<Config>::try_parse_as(json_config, ConfigFormat::Json).err(),
+            Some(Error::ConfigParse {
+                path: None,
+                line: Some(2),
+                column: Some(31),
+                key: Some("domain[0]".to_string()),
+                message: "missing field `host`".to_string(),
+            })
+        );
+
+        let result = ConfigParser::<Config>::try_parse_as(
+            "{\"test_subnets\": {}, \"domain\": []} []",
+            ConfigFormat::Json,
+        );
+        let Err(Error::ConfigParse { key, message, .. }) = result else {
+            panic!("the config should not parse");
+        };
+        assert_eq!((key, message.as_str()), (None, "trailing characters"));
+    }
+
+    #[test]
+    fn test_try_parse_env_vars() {
+        std::env::set_var("DNS_GEO_TEST_MMDB_DIR", "/srv/mmdb");
+        std::env::set_var("DNS_GEO_TEST_CORP_RESOLVER", "10.0.0.53");
+        let test_config = r#"
+            mmdb_path = "${DNS_GEO_TEST_MMDB_DIR}/GeoLite2-City.mmdb"
+            dns_resolver = "${DNS_GEO_TEST_RESOLVER:-cloudflare}"
+
+            [resolvers]
+            corp = { address = "${DNS_GEO_TEST_CORP_RESOLVER}", transport = "${DNS_GEO_TEST_UNSET_TRANSPORT:-udp}" }
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+        "#;
+
+        let config: Config = ConfigParser::try_parse(test_config).unwrap();
+        assert_eq!(
+            config.mmdb_path.as_deref(),
+            Some("/srv/mmdb/GeoLite2-City.mmdb")
+        );
+        assert_eq!(config.resolvers()[0].to_string(), "cloudflare");
+        assert_eq!(config.named_resolvers["corp"].address, "10.0.0.53");
+        assert_eq!(
+            config.named_resolvers["corp"].transport,
+            Some(DnsTransport::Udp)
+        );
+
+        let test_config = "test_subnets:\n  us: { subnets: [\"${DNS_GEO_TEST_UNSET}\"] }\n";
+        assert_eq!(
+            ConfigParser::<Config>::try_parse_as(test_config, ConfigFormat::Yaml).err(),
+            Some(Error::ConfigParse {
+                path: None,
+                line: Some(2),
+                column: Some(19),
+                key: Some("test_subnets.us.subnets[0]".to_string()),
+                message: "environment variable DNS_GEO_TEST_UNSET is not set".to_string(),
+            })
+        );
+    }
+
     #[test]
     fn test_parse() {
         let test_config = r#"
@@ -154,5 +1243,415 @@ mod tests {
             config.test_subnets.get("us").unwrap().subnets[0],
             "44.208.193.0/24"
         );
+        assert_eq!(config.domain[0].record_types, vec![DnsRecordType::A]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let test_config = r#"
+            max_concurrent_queries = 0
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+            sg = { subnets = ["175.41.192.0/18", "175.41.192.0", "2001:db8::/48"] }
+
+            [resolvers]
+            corp = { address = "10.0.0.53" }
+            broken = { address = "not an address" }
+
+            [[domain]]
+            host = "www.example.com"
+            geo_routing = ["us", "sg"]
+            resolvers = ["corp", "google"]
+
+            [[domain]]
+            host = "www.exa mple.com"
+            geo_routing = [
+                "us",
+                "uk",
+                { region = "my", expect = ["sg"] },
+                { region = "sg", expect = [] },
+            ]
+            outcomes = { cn = "nxdomain" }
+            resolvers = ["missing"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        let Err(Error::InvalidConfig(problems)) = config.validate() else {
+            panic!("the config should be invalid");
+        };
+        let locations: Vec<&str> = problems.iter().map(|p| p.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec![
+                "max_concurrent_queries",
+                "test_subnets.sg.subnets[1]",
+                "resolvers.broken",
+                "domain[1].host",
+                "domain[1].geo_routing[1]",
+                "domain[1].geo_routing[2]",
+                "domain[1].geo_routing[3].expect",
+                "domain[1].outcomes.cn",
+                "domain[1].resolvers[0]",
+            ]
+        );
+        assert_eq!(
+            problems[4].to_string(),
+            "domain[1].geo_routing[1]: region uk of www.exa mple.com is not defined in test_subnets"
+        );
+        assert_eq!(
+            problems[6].to_string(),
+            "domain[1].geo_routing[3].expect: region sg of www.exa mple.com expects no country"
+        );
+
+        let mut config = config;
+        config.max_concurrent_queries = None;
+        config.domain.truncate(1);
+        config.named_resolvers.remove("broken");
+        config.test_subnets.get_mut("sg").unwrap().subnets.remove(1);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_duplicate_hosts() {
+        let test_config = r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "www.example.com"
+            geo_routing = ["us"]
+
+            [[domain]]
+            host = "www.example.com"
+            geo_routing = ["us"]
+            record_types = ["AAAA"]
+        "#;
+
+        let mut config: Config = ConfigParser::parse(test_config.to_string());
+        assert_eq!(config.validate(), Ok(()));
+
+        config.domain.push(DomainConfig {
+            source: Some("domains/www.toml".to_string()),
+            ..config.domain[0].clone()
+        });
+        assert_eq!(
+            config.validate(),
+            Err(Error::InvalidConfig(vec![ConfigProblem::new(
+                "domains/www.toml, domain[0].host",
+                "duplicate host www.example.com, already defined at domain[0]",
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_parse_provider() {
+        let parse_error = |test_config: &str| match ConfigParser::<Config>::try_parse(test_config) {
+            Err(Error::ConfigParse { key, message, .. }) => Some((key.unwrap(), message)),
+            _ => None,
+        };
+
+        assert_eq!(
+            parse_error(r#"ip_geo_provider = "maxmind""#),
+            Some((
+                "ip_geo_provider".to_string(),
+                "unknown IP geo provider maxmind, expected mmdb or ip-api".to_string()
+            ))
+        );
+        #[cfg(not(feature = "ip-api"))]
+        assert_eq!(
+            parse_error(r#"ip_geo_provider = "ip-api""#),
+            Some((
+                "ip_geo_provider".to_string(),
+                "IP geo provider ip-api is not compiled in, enable its feature".to_string()
+            ))
+        );
+        #[cfg(feature = "ip-api")]
+        assert!(matches!(
+            ConfigParser::<Config>::try_parse(r#"ip_geo_provider = "ip-api""#),
+            Ok(Config {
+                ip_geo_provider: IpGeoProviderType::IpApi,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_validate_provider() {
+        let problems = |test_config: &str| -> Vec<String> {
+            let config: Config = ConfigParser::parse(test_config.to_string());
+            match config.validate() {
+                Err(Error::InvalidConfig(problems)) => {
+                    problems.iter().map(|p| p.to_string()).collect()
+                }
+                _ => vec![],
+            }
+        };
+
+        assert_eq!(
+            problems(r#"ip_geo_provider = "none""#),
+            vec![
+                "ip_geo_provider: no IP geo provider is selected, enable the mmdb or ip-api \
+                 feature"
+            ]
+        );
+        #[cfg(feature = "mmdb")]
+        {
+            assert_eq!(
+                problems("ip_geo_provider = \"MMDB\"\nmmdb_path = \"./missing.mmdb\""),
+                vec!["mmdb_path: MMDB file ./missing.mmdb not found"]
+            );
+            assert!(problems(r#"mmdb_path = "Cargo.toml""#).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_validate_ca_cert() {
+        let test_config = r#"
+            [resolvers]
+            corp-tls = { address = "dns.corp.example.com", transport = "tls", ca_cert = "Cargo.toml" }
+            corp-missing = { address = "10.0.0.53", transport = "tls", ca_cert = "./missing.pem" }
+            corp-udp = { address = "10.0.0.53", ca_cert = "Cargo.toml" }
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        let Err(Error::InvalidConfig(problems)) = config.validate() else {
+            panic!("the config should be invalid");
+        };
+        // Without the dns-over-tls feature the tls resolvers are invalid too
+        let problems: Vec<String> = problems
+            .iter()
+            .filter(|p| p.location.ends_with(".ca_cert"))
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "resolvers.corp-missing.ca_cert: CA certificate ./missing.pem not found",
+                "resolvers.corp-udp.ca_cert: a CA certificate is only used with the tls transport",
+            ]
+        );
+
+        #[cfg(feature = "dns-over-tls")]
+        {
+            let (resolver, _) = config.named_resolver("corp-tls").unwrap();
+            let DnsResolver::Tls(server) = resolver else {
+                panic!("corp-tls should be a DNS-over-TLS resolver");
+            };
+            assert_eq!(server.host, "dns.corp.example.com");
+            assert_eq!(server.ca_cert, Some(PathBuf::from("Cargo.toml")));
+        }
+    }
+
+    #[test]
+    fn test_parse_dns_resolver() {
+        let test_config = r#"
+            dns_resolver = "1.1.1.1"
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        assert_eq!(config.dns_resolver.unwrap().to_string(), "1.1.1.1:53");
+    }
+
+    #[test]
+    fn test_parse_dns_resolvers() {
+        let test_config = r#"
+            dns_resolver = "google"
+            dns_resolvers = ["cloudflare", "quad9", "192.0.2.53"]
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        let resolvers: Vec<String> = config.resolvers().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            resolvers,
+            vec!["google", "cloudflare", "quad9", "192.0.2.53:53"]
+        );
+    }
+
+    #[test]
+    fn test_parse_named_resolvers() {
+        let test_config = r#"
+            [dns_query]
+            timeout_ms = 1500
+
+            [resolvers]
+            corp = { address = "10.0.0.53" }
+            corp-tcp = { address = "10.0.0.53:5353", transport = "tcp", timeout_ms = 500 }
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "intranet.example.com"
+            geo_routing = ["us"]
+            resolvers = ["corp", "corp-tcp", "google"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        assert_eq!(config.domain[0].resolvers.len(), 3);
+
+        let (resolver, options) = config.named_resolver("corp").unwrap();
+        assert_eq!(resolver.to_string(), "10.0.0.53:53");
+        assert_eq!(options.timeout, Duration::from_millis(1500));
+        let (resolver, options) = config.named_resolver("corp-tcp").unwrap();
+        assert_eq!(resolver.to_string(), "tcp://10.0.0.53:5353");
+        assert_eq!(options.timeout, Duration::from_millis(500));
+        assert_eq!(options.retries, config.dns_query.retries);
+        let (resolver, _) = config.named_resolver("google").unwrap();
+        assert!(matches!(resolver, DnsResolver::Google));
+        assert_eq!(
+            config.named_resolver("unknown").unwrap_err(),
+            Error::UnknownResolver("unknown".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_dns_query() {
+        let test_config = r#"
+            [dns_query]
+            timeout_ms = 1500
+            retries = 3
+
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        assert_eq!(config.dns_query.timeout, Duration::from_millis(1500));
+        assert_eq!(config.dns_query.retries, 3);
+        assert_eq!(config.dns_query.backoff, DnsQueryOptions::default().backoff);
+    }
+
+    #[test]
+    fn test_parse_authoritative() {
+        let test_config = r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "www.example.com"
+            geo_routing = ["us"]
+            authoritative = true
+
+            [[domain]]
+            host = "www.example.org"
+            geo_routing = ["us"]
+            nameservers = ["ns1.example.org", "192.0.2.53"]
+
+            [[domain]]
+            host = "www.example.net"
+            geo_routing = ["us"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        assert!(config.domain[0].is_authoritative());
+        assert!(config.domain[0].nameservers.is_empty());
+        assert!(config.domain[1].is_authoritative());
+        assert_eq!(config.domain[1].nameservers.len(), 2);
+        assert!(!config.domain[2].is_authoritative());
+    }
+
+    #[test]
+    fn test_parse_min_scope_prefix() {
+        let test_config = r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+            min_scope_prefix = 24
+            max_ttl = 60
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        assert_eq!(config.domain[0].min_scope_prefix, Some(24));
+        assert_eq!(config.domain[0].max_ttl, Some(60));
+    }
+
+    #[test]
+    fn test_parse_outcomes() {
+        let test_config = r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24"] }
+            cn = { subnets = ["1.2.4.0/24"] }
+            ru = { subnets = ["5.8.0.0/24"] }
+
+            [[domain]]
+            host = "stream.example.com"
+            geo_routing = ["us", "cn", "ru"]
+            outcomes = { cn = "nxdomain", ru = "nodata" }
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        let domain = &config.domain[0];
+        assert_eq!(domain.outcomes.get("cn"), Some(&DnsOutcome::NxDomain));
+        assert_eq!(domain.expected("us"), ["us"]);
+        assert_eq!(domain.expected("cn"), ["nxdomain"]);
+        assert_eq!(domain.expected("ru"), ["nodata"]);
+    }
+
+    #[test]
+    fn test_parse_geo_routing_expect() {
+        let test_config = r#"
+            [test_subnets]
+            sg = { subnets = ["175.41.192.0/18"] }
+            my = { subnets = ["175.136.0.0/16"] }
+            id = { subnets = ["36.64.0.0/16"] }
+
+            [[domain]]
+            host = "www.example.com"
+            geo_routing = [
+                "sg",
+                { region = "my", expect = ["sg"] },
+                { region = "id", expect = ["sg", "jp"] },
+            ]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        let domain = &config.domain[0];
+        let regions: Vec<&str> = domain.geo_routing.iter().map(|r| r.region()).collect();
+        assert_eq!(regions, vec!["sg", "my", "id"]);
+        assert_eq!(domain.geo_routing[0], GeoRoutingConfig::from("sg"));
+        assert_eq!(domain.expected("sg"), ["sg"]);
+        assert_eq!(domain.expected("my"), ["sg"]);
+        assert_eq!(domain.expected("id"), ["sg", "jp"]);
+    }
+
+    #[test]
+    fn test_parse_record_types() {
+        let test_config = r#"
+            [test_subnets]
+            us = { subnets = ["44.208.193.0/24", "2600:1f18::/48"] }
+
+            [[domain]]
+            host = "google.com"
+            geo_routing = ["us"]
+            record_types = ["A", "AAAA"]
+        "#;
+
+        let config: Config = ConfigParser::parse(test_config.to_string());
+        assert_eq!(
+            config.domain[0].record_types,
+            vec![DnsRecordType::A, DnsRecordType::AAAA]
+        );
     }
 }
@@ -0,0 +1,132 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use hickory_proto::rr::{rdata::opt::ClientSubnet, RData, RecordType};
+
+use super::{display_name, parse_name, parse_socket_addr, DnsClient, DnsRecordType};
+use crate::error::{Error, Result};
+
+impl DnsClient {
+    /// Find the authoritative nameservers of the zone of a domain
+    ///
+    /// The NS records are looked up from the domain up to its second-level domain, the first
+    /// name with NS records is the zone. Returns the names of the nameservers.
+    pub async fn find_nameservers(&self, domain: &str) -> Result<Vec<String>> {
+        let mut name = parse_name(domain)?;
+        let mut attempts = 0;
+
+        while name.num_labels() > 1 {
+            let response = self
+                .query(&name, no_subnet(), RecordType::NS, &mut attempts)
+                .await?;
+            let nameservers: Vec<String> = response
+                .message
+                .answers()
+                .iter()
+                .filter(|record| record.name() == &name)
+                .filter_map(|record| match record.data() {
+                    Some(RData::NS(ns)) => Some(display_name(&ns.0)),
+                    _ => None,
+                })
+                .collect();
+            if !nameservers.is_empty() {
+                return Ok(nameservers);
+            }
+            name = name.base_name();
+        }
+
+        Err(Error::NoNameservers(domain.to_string()))
+    }
+
+    /// Get the address of a nameserver given either by address or by name
+    ///
+    /// Names are resolved to their first IPv4 address, or their first IPv6 address if they
+    /// have none. The port defaults to 53.
+    pub async fn resolve_nameserver(&self, nameserver: &str) -> Result<SocketAddr> {
+        if let Some(addr) = parse_socket_addr(nameserver, 53) {
+            return Ok(addr);
+        }
+
+        for record_type in [DnsRecordType::A, DnsRecordType::AAAA] {
+            let answer = self
+                .resolve_with_subnet_and_type(nameserver, "0.0.0.0/0", record_type)
+                .await?;
+            if let Some(ip) = answer.ips.first() {
+                return Ok(SocketAddr::new(*ip, 53));
+            }
+        }
+
+        Err(Error::Dns {
+            name: nameserver.to_string(),
+            message: "the nameserver has no address".to_string(),
+        })
+    }
+}
+
+/// A client subnet with a source prefix of 0, asking the server not to use the client address
+fn no_subnet() -> ClientSubnet {
+    ClientSubnet::new(Ipv4Addr::UNSPECIFIED.into(), 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_client::tests::{address_answer, response_with, spawn_udp_server};
+    use crate::dns_client::DnsResolver;
+    use hickory_proto::op::Message;
+    use hickory_proto::rr::rdata;
+    use hickory_resolver::Name;
+
+    /// Answer NS queries for example.com and address queries for anything else
+    fn zone_answer(request: &Message) -> Message {
+        let query = &request.queries()[0];
+        match query.query_type() {
+            RecordType::NS if query.name() == &Name::from_ascii("example.com").unwrap() => {
+                response_with(
+                    request,
+                    ["ns1.example.com.", "ns2.example.com."]
+                        .iter()
+                        .map(|ns| RData::NS(rdata::NS(Name::from_ascii(ns).unwrap())))
+                        .collect(),
+                )
+            }
+            RecordType::NS => response_with(request, vec![]),
+            _ => address_answer(request),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_nameservers() {
+        let addr = spawn_udp_server(zone_answer).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let nameservers = client.find_nameservers("www.example.com").await.unwrap();
+        assert_eq!(
+            nameservers,
+            vec!["ns1.example.com".to_string(), "ns2.example.com".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_nameservers_none() {
+        let addr = spawn_udp_server(|request| response_with(request, vec![])).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client.find_nameservers("www.example.org").await;
+        assert_eq!(
+            result,
+            Err(Error::NoNameservers("www.example.org".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_nameserver() {
+        let addr = spawn_udp_server(zone_answer).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        assert_eq!(
+            client.resolve_nameserver("ns1.example.com").await,
+            Ok("192.0.2.1:53".parse().unwrap())
+        );
+        assert_eq!(
+            client.resolve_nameserver("192.0.2.53:5353").await,
+            Ok("192.0.2.53:5353".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use serde::Serialize;
+
+use super::{DnsClient, DnsRecordType};
+use crate::error::Result;
+
+/// The subnet sent in the probe, any routable subnet works
+const PROBE_SUBNET: &str = "24.24.24.0/24";
+
+/// The support of a resolver for the EDNS client subnet option
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EcsSupport {
+    /// The resolver echoed the option, even with a scope of 0 for a domain which is not
+    /// geo-routed
+    Supported,
+    /// The resolver did not echo the option, it strips or ignores it
+    Unsupported,
+}
+
+impl DnsClient {
+    /// Probe whether the resolver forwards the EDNS client subnet option for a domain
+    ///
+    /// The resolver is considered to support the option if it echoes it in its response, as
+    /// required by RFC 7871. The answers themselves say nothing about the support, a domain
+    /// which is not geo-routed gets the same answer with a scope of 0 from every subnet.
+    pub async fn probe_ecs_support(
+        &self,
+        domain: &str,
+        record_type: DnsRecordType,
+    ) -> Result<EcsSupport> {
+        let answer = self
+            .resolve_with_subnet_and_type(domain, PROBE_SUBNET, record_type)
+            .await?;
+        if answer.ecs.is_some() {
+            Ok(EcsSupport::Supported)
+        } else {
+            Ok(EcsSupport::Unsupported)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_client::tests::{address_answer, response_with, spawn_udp_server};
+    use crate::dns_client::DnsResolver;
+    use hickory_proto::op::{Edns, Message};
+    use hickory_proto::rr::rdata::opt::{ClientSubnet, EdnsCode, EdnsOption};
+    use hickory_proto::rr::{rdata, RData};
+    use std::net::Ipv4Addr;
+
+    /// Answer with the first octet of the client subnet as the last octet of the address
+    fn subnet_answer(request: &Message) -> Message {
+        let subnet = request
+            .extensions()
+            .as_ref()
+            .and_then(|edns| edns.option(EdnsCode::Subnet))
+            .cloned();
+        let octet = match subnet {
+            Some(EdnsOption::Subnet(subnet)) => Vec::<u8>::try_from(&subnet).unwrap()[4],
+            _ => 0,
+        };
+        response_with(
+            request,
+            vec![RData::A(rdata::A(Ipv4Addr::new(192, 0, 2, octet)))],
+        )
+    }
+
+    /// Answer with the client subnet option echoed with the given scope
+    fn echo_answer(request: &Message, scope: u8) -> Message {
+        let mut edns = Edns::new();
+        edns.options_mut()
+            .insert(EdnsOption::Subnet(ClientSubnet::new(
+                Ipv4Addr::new(24, 24, 24, 0).into(),
+                24,
+                scope,
+            )));
+        let mut response = address_answer(request);
+        response.set_edns(edns);
+        response
+    }
+
+    #[tokio::test]
+    async fn test_probe_ecs_support_scope() {
+        let addr = spawn_udp_server(|request| echo_answer(request, 16)).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let support = client.probe_ecs_support("www.example.com", DnsRecordType::A);
+        assert_eq!(support.await, Ok(EcsSupport::Supported));
+    }
+
+    #[tokio::test]
+    async fn test_probe_ecs_support_scope_zero() {
+        let addr = spawn_udp_server(|request| echo_answer(request, 0)).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let support = client.probe_ecs_support("www.example.com", DnsRecordType::A);
+        assert_eq!(support.await, Ok(EcsSupport::Supported));
+    }
+
+    #[tokio::test]
+    async fn test_probe_ecs_support_no_echo() {
+        let addr = spawn_udp_server(subnet_answer).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let support = client.probe_ecs_support("www.example.com", DnsRecordType::A);
+        assert_eq!(support.await, Ok(EcsSupport::Unsupported));
+    }
+}
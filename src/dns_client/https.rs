@@ -0,0 +1,322 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hickory_proto::op::Message;
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE},
+    Url,
+};
+
+use crate::error::{Error, Result};
+
+/// The media type of DNS messages sent over HTTPS
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// The HTTP method used to send DNS-over-HTTPS queries, see RFC 8484 section 4.1
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DohMethod {
+    /// Send the query base64url-encoded in the `dns` query parameter
+    Get,
+    /// Send the query as the request body
+    #[default]
+    Post,
+}
+
+/// A DNS-over-HTTPS server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsHttpsServer {
+    /// The URL of the DNS query endpoint, e.g. `https://dns.google/dns-query`
+    pub url: Url,
+    /// The HTTP method used to send the queries
+    pub method: DohMethod,
+}
+
+impl DnsHttpsServer {
+    /// Create a new DNS-over-HTTPS server sending the queries with POST
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            method: DohMethod::Post,
+        }
+    }
+
+    /// Send the queries with the given HTTP method
+    pub fn with_method(mut self, method: DohMethod) -> Self {
+        self.method = method;
+        self
+    }
+}
+
+impl fmt::Display for DnsHttpsServer {
+    /// Display the URL of the server, with the `https+get://` scheme for the GET method
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.method {
+            DohMethod::Get => {
+                let scheme = self.url.scheme();
+                write!(f, "{}+get{}", scheme, &self.url.as_str()[scheme.len()..])
+            }
+            DohMethod::Post => write!(f, "{}", self.url),
+        }
+    }
+}
+
+impl FromStr for DnsHttpsServer {
+    type Err = Error;
+
+    /// Parse a server from its `https://` URL, `https+get://` selects the GET method
+    fn from_str(s: &str) -> Result<Self> {
+        let (url, method) = match s.strip_prefix("https+get://") {
+            Some(rest) => (format!("https://{}", rest), DohMethod::Get),
+            None => (s.to_string(), DohMethod::Post),
+        };
+        let url = Url::parse(&url)
+            .ok()
+            .filter(|url| url.scheme() == "https" && url.has_host())
+            .ok_or(Error::InvalidResolver(s.to_string()))?;
+
+        Ok(Self::new(url).with_method(method))
+    }
+}
+
+/// A client sending DNS messages over HTTPS
+#[derive(Clone, Debug)]
+pub(crate) struct DohClient {
+    server: DnsHttpsServer,
+    client: reqwest::Client,
+}
+
+impl DohClient {
+    /// Create a new client for the server, waiting up to `timeout` for every response
+    pub(crate) fn new(server: &DnsHttpsServer, timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| Error::Connect {
+                resolver: server.to_string(),
+                message: e.to_string(),
+            })?;
+
+        Ok(Self {
+            server: server.clone(),
+            client,
+        })
+    }
+
+    /// Send a DNS message and wait for the response
+    ///
+    /// `name` is the queried name, used to report errors.
+    pub(crate) async fn send(&self, mut msg: Message, name: &str) -> Result<Message> {
+        // RFC 8484 recommends an ID of 0 to make the responses cache friendly
+        msg.set_id(0);
+        let query = msg.to_vec().map_err(|e| self.error(name, e))?;
+
+        let request = match self.server.method {
+            DohMethod::Get => self
+                .client
+                .get(self.server.url.clone())
+                .query(&[("dns", URL_SAFE_NO_PAD.encode(query))]),
+            DohMethod::Post => self
+                .client
+                .post(self.server.url.clone())
+                .header(CONTENT_TYPE, DNS_MESSAGE)
+                .body(query),
+        };
+
+        let response = request
+            .header(ACCEPT, DNS_MESSAGE)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| {
+                if e.is_timeout() {
+                    Error::DnsTimeout(name.to_string())
+                } else if e.status().is_some_and(|status| status.is_client_error()) {
+                    // The server rejected the query, sending it again will not help
+                    self.error(name, e)
+                } else {
+                    self.transport_error(name, e)
+                }
+            })?;
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| self.transport_error(name, e))?;
+
+        Message::from_vec(&body).map_err(|e| self.error(name, e))
+    }
+
+    fn error<E: ToString>(&self, name: &str, e: E) -> Error {
+        Error::Dns {
+            name: name.to_string(),
+            message: format!("{}: {}", self.server, e.to_string()),
+        }
+    }
+
+    fn transport_error<E: ToString>(&self, name: &str, e: E) -> Error {
+        Error::Transport {
+            name: name.to_string(),
+            message: format!("{}: {}", self.server, e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_client::tests::address_answer;
+    use crate::dns_client::{DnsQueryOptions, DnsRecordType, DnsResolver};
+    use hickory_proto::rr::rdata::opt::EdnsCode;
+    use std::net::IpAddr;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Spawn a local plain HTTP DNS query endpoint answering with the body built by `answer`
+    ///
+    /// Every request is recorded as its method and whether it carried a client subnet.
+    async fn spawn_doh_server<F>(answer: F) -> (Url, Arc<Mutex<Vec<(String, bool)>>>)
+    where
+        F: Fn(&Message) -> Vec<u8> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/dns-query",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let c_requests = requests.clone();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut stream = BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).await.unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((key, value)) = header.split_once(':') {
+                        if key.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+
+                let mut request_line = request_line.split_whitespace();
+                let (method, target) = (request_line.next().unwrap(), request_line.next().unwrap());
+                let query = match method {
+                    "GET" => {
+                        let target = Url::parse("http://localhost")
+                            .unwrap()
+                            .join(target)
+                            .unwrap();
+                        let (_, dns) = target.query_pairs().find(|(k, _)| k == "dns").unwrap();
+                        URL_SAFE_NO_PAD.decode(dns.as_bytes()).unwrap()
+                    }
+                    _ => {
+                        let mut body = vec![0; content_length];
+                        stream.read_exact(&mut body).await.unwrap();
+                        body
+                    }
+                };
+
+                let request = Message::from_vec(&query).unwrap();
+                let has_subnet = request
+                    .extensions()
+                    .as_ref()
+                    .is_some_and(|edns| edns.option(EdnsCode::Subnet).is_some());
+                c_requests
+                    .lock()
+                    .unwrap()
+                    .push((method.to_string(), has_subnet));
+
+                let response = answer(&request);
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    DNS_MESSAGE,
+                    response.len()
+                );
+                let stream = stream.get_mut();
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[test]
+    fn test_dns_https_server_from_str() {
+        let server: DnsHttpsServer = "https://dns.google/dns-query".parse().unwrap();
+        assert_eq!(server.url.as_str(), "https://dns.google/dns-query");
+        assert_eq!(server.method, DohMethod::Post);
+        let server: DnsHttpsServer = "https+get://dns.google/dns-query".parse().unwrap();
+        assert_eq!(server.url.as_str(), "https://dns.google/dns-query");
+        assert_eq!(server.method, DohMethod::Get);
+        assert!("https://".parse::<DnsHttpsServer>().is_err());
+        assert!("ftp://dns.google".parse::<DnsHttpsServer>().is_err());
+        assert!("http://dns.google/dns-query"
+            .parse::<DnsHttpsServer>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_dns_https_server_display() {
+        for s in [
+            "https://dns.google/dns-query",
+            "https+get://dns.google/dns-query",
+        ] {
+            let server: DnsHttpsServer = s.parse().unwrap();
+            assert_eq!(server.to_string(), s);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_over_https() {
+        let (url, requests) =
+            spawn_doh_server(|request| address_answer(request).to_vec().unwrap()).await;
+
+        for method in [DohMethod::Post, DohMethod::Get] {
+            let server = DnsHttpsServer::new(url.clone()).with_method(method);
+            let client = DnsResolver::Https(server).try_connect().await.unwrap();
+            let result = client
+                .resolve_with_subnet_and_type("example.com", "24.24.24.0/24", DnsRecordType::A)
+                .await
+                .unwrap();
+            assert_eq!(result.ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+            assert_eq!(result.server, None);
+        }
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![("POST".to_string(), true), ("GET".to_string(), true)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_over_https_malformed_response() {
+        let (url, requests) = spawn_doh_server(|_| b"not a DNS message".to_vec()).await;
+        let options = DnsQueryOptions {
+            timeout: Duration::from_millis(500),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        };
+        let client = DnsResolver::Https(DnsHttpsServer::new(url))
+            .try_connect_with_options(options)
+            .await
+            .unwrap();
+        let failure = client
+            .resolve_detailed("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap_err();
+
+        // A malformed response is not retried
+        assert_eq!(failure.error.kind(), "dns");
+        assert_eq!(failure.attempts, 1);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,1403 @@
+#![allow(dead_code)]
+
+use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hickory_client::client::AsyncClient;
+use hickory_proto::error::ProtoErrorKind;
+use hickory_proto::iocompat::AsyncIoTokioAsStd;
+use hickory_proto::rr::rdata::opt::ClientSubnet;
+use hickory_proto::rr::{RData, Record};
+use hickory_proto::xfer::{DnsResponse, FirstAnswer};
+use hickory_proto::DnsHandle;
+use hickory_proto::{
+    op::{Edns, Message, MessageType, OpCode, Query, ResponseCode},
+    rr::{
+        rdata::opt::{EdnsCode, EdnsOption},
+        DNSClass, RecordType,
+    },
+    tcp::TcpClientStream,
+    udp::UdpClientStream,
+};
+use hickory_resolver::Name;
+use serde::{Deserialize, Deserializer, Serialize};
+
+mod authoritative;
+mod ecs_probe;
+pub use ecs_probe::EcsSupport;
+#[cfg(feature = "dns-over-https")]
+pub mod https;
+#[cfg(feature = "dns-over-https")]
+use https::{DnsHttpsServer, DohClient};
+#[cfg(feature = "dns-over-tls")]
+pub mod tls;
+#[cfg(feature = "dns-over-tls")]
+use tls::DnsTlsServer;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::OnceCell;
+
+use crate::error::{Error, Result};
+
+/// The type of address record to query
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum DnsRecordType {
+    /// IPv4 address record
+    #[default]
+    #[serde(alias = "a")]
+    A,
+    /// IPv6 address record
+    #[serde(alias = "aaaa")]
+    AAAA,
+}
+
+impl From<DnsRecordType> for RecordType {
+    fn from(record_type: DnsRecordType) -> Self {
+        match record_type {
+            DnsRecordType::A => RecordType::A,
+            DnsRecordType::AAAA => RecordType::AAAA,
+        }
+    }
+}
+
+impl FromStr for DnsRecordType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(DnsRecordType::A),
+            "AAAA" => Ok(DnsRecordType::AAAA),
+            _ => Err(Error::InvalidRecordType(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for DnsRecordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsRecordType::A => write!(f, "A"),
+            DnsRecordType::AAAA => write!(f, "AAAA"),
+        }
+    }
+}
+
+/// An answer without addresses, which a domain may be expected to give to some geos, e.g. for
+/// geo-blocked services
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsOutcome {
+    /// The domain does not exist
+    NxDomain,
+    /// The domain exists but has no records of the queried type
+    NoData,
+}
+
+impl DnsOutcome {
+    /// Get the outcome behind an error, `None` if the error is not one of the outcomes
+    pub fn from_error(error: &Error) -> Option<Self> {
+        match error {
+            Error::NxDomain(_) => Some(DnsOutcome::NxDomain),
+            Error::NoData(_) => Some(DnsOutcome::NoData),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DnsOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsOutcome::NxDomain => write!(f, "nxdomain"),
+            DnsOutcome::NoData => write!(f, "nodata"),
+        }
+    }
+}
+
+/// The address of a DNS server
+///
+/// This can be either a predefined server or a custom one
+#[derive(Clone, Debug)]
+pub enum DnsServerAddr {
+    /// Google's public DNS server
+    Google,
+    /// CloudFlare's public DNS server
+    CloudFlare,
+    /// Quad9's public DNS server forwarding the EDNS client subnet
+    Quad9,
+    /// Custom DNS server
+    Custom(SocketAddr),
+    /// DNS server queried over TCP only
+    Tcp(SocketAddr),
+    /// DNS-over-TLS server
+    #[cfg(feature = "dns-over-tls")]
+    Tls(DnsTlsServer),
+    /// DNS-over-HTTPS server
+    #[cfg(feature = "dns-over-https")]
+    Https(DnsHttpsServer),
+}
+
+impl DnsServerAddr {
+    /// Get the address of the DNS server
+    ///
+    /// `None` for a DNS-over-HTTPS server, which is only known by its URL, and for a
+    /// DNS-over-TLS server known by its host name until it is resolved when connecting.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        match self {
+            DnsServerAddr::Google => ("8.8.8.8", 53).to_socket_addrs().unwrap().next(),
+            DnsServerAddr::CloudFlare => ("1.1.1.1", 53).to_socket_addrs().unwrap().next(),
+            // 9.9.9.9 strips the client subnet, 9.9.9.11 is the same service forwarding it
+            DnsServerAddr::Quad9 => ("9.9.9.11", 53).to_socket_addrs().unwrap().next(),
+            DnsServerAddr::Custom(addr) | DnsServerAddr::Tcp(addr) => Some(*addr),
+            #[cfg(feature = "dns-over-tls")]
+            DnsServerAddr::Tls(server) => server.addr(),
+            #[cfg(feature = "dns-over-https")]
+            DnsServerAddr::Https(_) => None,
+        }
+    }
+}
+
+/// The timeouts and retries of the queries sent by a [`DnsClient`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct DnsQueryOptions {
+    /// The time to wait for the response to a single attempt
+    #[serde(rename = "timeout_ms", deserialize_with = "deserialize_millis")]
+    pub timeout: Duration,
+    /// The number of attempts made after a timeout or a transport error, on top of the first
+    pub retries: u32,
+    /// The delay before the first retry, doubled before every further retry
+    #[serde(rename = "backoff_ms", deserialize_with = "deserialize_millis")]
+    pub backoff: Duration,
+}
+
+impl Default for DnsQueryOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl DnsQueryOptions {
+    /// Get the delay before the given retry, starting at 0
+    fn backoff(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+fn deserialize_millis<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+/// The transport a DNS message was sent over
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsTransport {
+    #[default]
+    Udp,
+    Tcp,
+    Tls,
+    Https,
+}
+
+impl std::fmt::Display for DnsTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsTransport::Udp => write!(f, "udp"),
+            DnsTransport::Tcp => write!(f, "tcp"),
+            DnsTransport::Tls => write!(f, "tls"),
+            DnsTransport::Https => write!(f, "https"),
+        }
+    }
+}
+
+/// The transport DNS messages are sent over
+#[derive(Clone)]
+enum Transport {
+    /// A client over UDP, retrying over TCP when a response is truncated
+    Udp {
+        udp: Arc<AsyncClient>,
+        tcp: Arc<TcpFallback>,
+    },
+    /// A client over a stream, TCP or TLS
+    Stream(Arc<AsyncClient>, DnsTransport),
+    /// A client over HTTPS
+    #[cfg(feature = "dns-over-https")]
+    Https(DohClient),
+}
+
+/// A TCP client to the server of a UDP client, only connected on the first truncated response
+struct TcpFallback {
+    addr: SocketAddr,
+    timeout: Duration,
+    client: OnceCell<AsyncClient>,
+}
+
+impl TcpFallback {
+    fn new(addr: SocketAddr, timeout: Duration) -> Self {
+        Self {
+            addr,
+            timeout,
+            client: OnceCell::new(),
+        }
+    }
+
+    /// Get the TCP client, connecting it if needed
+    async fn client(&self) -> Result<&AsyncClient> {
+        self.client
+            .get_or_try_init(|| connect_tcp(self.addr, self.timeout))
+            .await
+    }
+}
+
+/// Connect a client to a DNS server over TCP, waiting up to `timeout` for every response
+async fn connect_tcp(addr: SocketAddr, timeout: Duration) -> Result<AsyncClient> {
+    let (stream, handle) =
+        TcpClientStream::<AsyncIoTokioAsStd<TcpStream>>::with_timeout(addr, timeout);
+    let (client, bg) = AsyncClient::with_timeout(stream, handle, timeout, None)
+        .await
+        .map_err(|e| Error::Connect {
+            resolver: addr.to_string(),
+            message: e.to_string(),
+        })?;
+    tokio::spawn(bg);
+    Ok(client)
+}
+
+#[derive(Clone)]
+pub struct DnsClient {
+    /// The DNS client
+    client: Transport,
+    /// The address of the DNS server, `None` for a DNS-over-HTTPS server
+    server: Option<SocketAddr>,
+    /// The timeouts and retries of the queries
+    options: DnsQueryOptions,
+}
+
+impl DnsClient {
+    /// Create a new DNS client
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client fails to connect, see [`DnsClient::try_new`].
+    pub async fn new(resolver: DnsServerAddr) -> Self {
+        Self::try_new(resolver)
+            .await
+            .expect("client failed to connect")
+    }
+
+    /// Create a new DNS client, failing if it cannot connect to the server
+    pub async fn try_new(resolver: DnsServerAddr) -> Result<Self> {
+        Self::try_new_with_options(resolver, DnsQueryOptions::default()).await
+    }
+
+    /// Create a new DNS client with the given timeouts and retries, failing if it cannot
+    /// connect to the server
+    pub async fn try_new_with_options(
+        resolver: DnsServerAddr,
+        options: DnsQueryOptions,
+    ) -> Result<Self> {
+        let server = resolver.addr();
+        let (client, server) = match (&resolver, server) {
+            (DnsServerAddr::Tcp(addr), _) => (
+                Transport::Stream(
+                    Arc::new(connect_tcp(*addr, options.timeout).await?),
+                    DnsTransport::Tcp,
+                ),
+                server,
+            ),
+            #[cfg(feature = "dns-over-tls")]
+            (DnsServerAddr::Tls(tls), _) => {
+                let (client, addr) = tls.connect(options.timeout).await?;
+                (
+                    Transport::Stream(Arc::new(client), DnsTransport::Tls),
+                    Some(addr),
+                )
+            }
+            #[cfg(feature = "dns-over-https")]
+            (DnsServerAddr::Https(url), _) => (
+                Transport::Https(DohClient::new(url, options.timeout)?),
+                None,
+            ),
+            (_, None) => {
+                unreachable!("only DNS-over-TLS and DNS-over-HTTPS servers can have no address")
+            }
+            (_, Some(addr)) => {
+                let stream = UdpClientStream::<UdpSocket>::with_timeout(addr, options.timeout);
+                let (client, bg) =
+                    AsyncClient::connect(stream)
+                        .await
+                        .map_err(|e| Error::Connect {
+                            resolver: addr.to_string(),
+                            message: e.to_string(),
+                        })?;
+                tokio::spawn(bg);
+                (
+                    Transport::Udp {
+                        udp: Arc::new(client),
+                        tcp: Arc::new(TcpFallback::new(addr, options.timeout)),
+                    },
+                    server,
+                )
+            }
+        };
+
+        Ok(Self {
+            client,
+            server,
+            options,
+        })
+    }
+
+    /// Resolve the A records of a domain with a subnet
+    pub async fn resolve_with_subnet(&self, domain: &str, subnet: &str) -> Result<Vec<IpAddr>> {
+        Ok(self
+            .resolve_with_subnet_and_type(domain, subnet, DnsRecordType::A)
+            .await?
+            .ips)
+    }
+
+    /// Resolve a domain with a subnet for the given record type
+    ///
+    /// The subnet can be either an IPv4 or an IPv6 CIDR, independently of the record type.
+    /// CNAME records are followed, re-querying the last target if the server did not include
+    /// its addresses in the answer. Truncated UDP responses are retried over TCP, the transport
+    /// of the last query is recorded on the answer, along with its response code, flags and
+    /// response time. Timeouts and transport errors are retried according to the
+    /// [`DnsQueryOptions`] of the client.
+    pub async fn resolve_with_subnet_and_type(
+        &self,
+        domain: &str,
+        subnet: &str,
+        record_type: DnsRecordType,
+    ) -> Result<DnsAnswer> {
+        self.resolve_detailed(domain, subnet, record_type)
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Resolve a domain with a subnet for the given record type, like
+    /// [`DnsClient::resolve_with_subnet_and_type`]
+    ///
+    /// A failed resolution still reports the number of messages sent and the time taken.
+    pub async fn resolve_detailed(
+        &self,
+        domain: &str,
+        subnet: &str,
+        record_type: DnsRecordType,
+    ) -> std::result::Result<DnsAnswer, DnsQueryFailure> {
+        let mut answer = DnsAnswer {
+            server: self.server,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        match self
+            .resolve_into(&mut answer, domain, subnet, record_type)
+            .await
+        {
+            Ok(()) => {
+                answer.latency = start.elapsed();
+                Ok(answer)
+            }
+            Err(error) => Err(DnsQueryFailure {
+                error,
+                attempts: answer.attempts,
+                latency: start.elapsed(),
+            }),
+        }
+    }
+
+    /// Resolve a domain, recording the responses on the answer as they are received
+    async fn resolve_into(
+        &self,
+        answer: &mut DnsAnswer,
+        domain: &str,
+        subnet: &str,
+        record_type: DnsRecordType,
+    ) -> Result<()> {
+        let subnet = parse_subnet(subnet)?;
+        let mut name = parse_name(domain)?;
+
+        for _ in 0..MAX_CNAME_QUERIES {
+            let response = self
+                .query(&name, subnet, record_type.into(), &mut answer.attempts)
+                .await?;
+            answer.transport = response.transport;
+            answer.response_time = response.time;
+            answer.rcode = rcode_name(response.message.response_code());
+            answer.flags = DnsFlags::from_response(&response.message);
+            answer.ecs = EcsScope::from_response(&response.message);
+            let chain_len = answer.cname_chain.len();
+            name = answer.follow(name, response.message.answers());
+
+            // Only re-query when the answer ended on a CNAME target without its addresses
+            if !answer.ips.is_empty()
+                || answer.cname_chain.len() == chain_len
+                || answer.cname_chain.len() >= MAX_CNAME_CHAIN
+            {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send a message over the transport of the client
+    ///
+    /// Returns the response along with the transport it was received over.
+    async fn send(&self, msg: Message, name: &Name) -> Result<(Message, DnsTransport)> {
+        match &self.client {
+            Transport::Udp { udp, tcp } => {
+                let response = send_stream(udp, msg.clone(), name).await?;
+                if !response.truncated() {
+                    return Ok((response, DnsTransport::Udp));
+                }
+                let client = tcp.client().await?;
+                Ok((send_stream(client, msg, name).await?, DnsTransport::Tcp))
+            }
+            Transport::Stream(client, transport) => {
+                Ok((send_stream(client, msg, name).await?, *transport))
+            }
+            #[cfg(feature = "dns-over-https")]
+            Transport::Https(client) => Ok((
+                client.send(msg, &display_name(name)).await?,
+                DnsTransport::Https,
+            )),
+        }
+    }
+
+    /// Send a message, retrying with a backoff after timeouts and transport errors
+    ///
+    /// Every message sent is counted in `attempts`.
+    async fn send_with_retries(
+        &self,
+        mut msg: Message,
+        name: &Name,
+        attempts: &mut u32,
+    ) -> Result<Response> {
+        let mut retry = 0;
+        loop {
+            *attempts += 1;
+            let start = Instant::now();
+            let result = tokio::time::timeout(self.options.timeout, self.send(msg.clone(), name))
+                .await
+                .unwrap_or_else(|_| Err(Error::DnsTimeout(display_name(name))))
+                .map(|(message, transport)| Response {
+                    message,
+                    transport,
+                    time: start.elapsed(),
+                });
+
+            match result {
+                Err(e) if e.is_transient() && retry < self.options.retries => {
+                    tokio::time::sleep(self.options.backoff(retry)).await;
+                    retry += 1;
+                    msg.set_id(rand::random::<u16>());
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a single query carrying the EDNS client subnet option
+    ///
+    /// Error response codes are turned into errors.
+    async fn query(
+        &self,
+        name: &Name,
+        subnet: ClientSubnet,
+        record_type: RecordType,
+        attempts: &mut u32,
+    ) -> Result<Response> {
+        let mut edns = Edns::new();
+        edns.options_mut().insert(EdnsOption::Subnet(subnet));
+
+        let mut msg = Message::new();
+        msg.add_query({
+            let mut query = Query::query(name.clone(), record_type);
+            query.set_query_class(DNSClass::IN);
+            query
+        })
+        .set_id(rand::random::<u16>())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .set_edns(edns)
+        .extensions_mut()
+        .get_or_insert_with(Edns::new)
+        .set_max_payload(1232)
+        .set_version(0);
+
+        let response = self.send_with_retries(msg, name, attempts).await?;
+
+        match response.message.response_code() {
+            ResponseCode::NoError => Ok(response),
+            ResponseCode::NXDomain => Err(Error::NxDomain(display_name(name))),
+            ResponseCode::ServFail => Err(Error::ServFail(display_name(name))),
+            code => Err(Error::Rcode {
+                name: display_name(name),
+                rcode: rcode_name(code),
+            }),
+        }
+    }
+}
+
+/// A response to a query and how it was received
+struct Response {
+    /// The response message
+    message: Message,
+    /// The transport the response was received over
+    transport: DnsTransport,
+    /// The time taken by the attempt which received the response
+    time: Duration,
+}
+
+/// Get the mnemonic of a response code, e.g. `NOERROR`
+fn rcode_name(code: ResponseCode) -> String {
+    match code {
+        ResponseCode::NoError => "NOERROR".to_string(),
+        ResponseCode::FormErr => "FORMERR".to_string(),
+        ResponseCode::ServFail => "SERVFAIL".to_string(),
+        ResponseCode::NXDomain => "NXDOMAIN".to_string(),
+        ResponseCode::NotImp => "NOTIMP".to_string(),
+        ResponseCode::Refused => "REFUSED".to_string(),
+        code => format!("RCODE{}", u16::from(code)),
+    }
+}
+
+/// Send a message with a client over a UDP, TCP or TLS stream
+async fn send_stream(client: &AsyncClient, msg: Message, name: &Name) -> Result<Message> {
+    client
+        .send(msg)
+        .first_answer()
+        .await
+        .map(DnsResponse::into_message)
+        .map_err(|e| match e.kind() {
+            ProtoErrorKind::Timeout => Error::DnsTimeout(display_name(name)),
+            ProtoErrorKind::Io(_) | ProtoErrorKind::Busy | ProtoErrorKind::Canceled(_) => {
+                Error::Transport {
+                    name: display_name(name),
+                    message: e.to_string(),
+                }
+            }
+            _ => Error::Dns {
+                name: display_name(name),
+                message: e.to_string(),
+            },
+        })
+}
+
+/// The maximum number of queries sent to resolve a CNAME chain
+const MAX_CNAME_QUERIES: usize = 4;
+/// The maximum length of a CNAME chain before giving up
+const MAX_CNAME_CHAIN: usize = 16;
+
+/// The answer to an address query
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DnsAnswer {
+    /// The addresses of the final name in the chain
+    pub ips: Vec<IpAddr>,
+    /// The canonical names followed from the queried name, in order
+    pub cname_chain: Vec<String>,
+    /// The transport the last response was received over
+    pub transport: DnsTransport,
+    /// The number of messages sent, including retries
+    pub attempts: u32,
+    /// The time taken to resolve the answer, including retries
+    pub latency: Duration,
+    /// The client subnet echoed by the server in the last response, `None` if it did not echo
+    /// the option
+    pub ecs: Option<EcsScope>,
+    /// The CNAME and address records of the chain with their TTLs, in order
+    pub records: Vec<DnsRecord>,
+    /// The response code of the last response, e.g. `NOERROR`
+    pub rcode: String,
+    /// The header flags of the last response
+    pub flags: DnsFlags,
+    /// The time taken by the attempt which received the last response
+    pub response_time: Duration,
+    /// The address of the server the queries were sent to, `None` for a DNS-over-HTTPS server
+    pub server: Option<SocketAddr>,
+}
+
+/// A resolution which failed, with the messages sent before giving up
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsQueryFailure {
+    /// The error the resolution failed with
+    pub error: Error,
+    /// The number of messages sent, including retries
+    pub attempts: u32,
+    /// The time taken before giving up, including retries
+    pub latency: Duration,
+}
+
+/// A record of an answer
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DnsRecord {
+    /// The owner name of the record
+    pub name: String,
+    /// The type of the record, `A`, `AAAA` or `CNAME`
+    pub record_type: String,
+    /// The address or the canonical name of the record
+    pub data: String,
+    /// The time the record may be cached for, in seconds
+    pub ttl: u32,
+}
+
+impl DnsRecord {
+    fn new(record: &Record, data: String) -> Self {
+        Self {
+            name: display_name(record.name()),
+            record_type: record.record_type().to_string(),
+            data,
+            ttl: record.ttl(),
+        }
+    }
+}
+
+/// The header flags of a response
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DnsFlags {
+    /// The server is authoritative for the answer (AA)
+    pub authoritative: bool,
+    /// The server supports recursive queries (RA)
+    pub recursion_available: bool,
+    /// The answer was validated with DNSSEC by the server (AD)
+    pub authentic_data: bool,
+}
+
+impl DnsFlags {
+    fn from_response(msg: &Message) -> Self {
+        Self {
+            authoritative: msg.authoritative(),
+            recursion_available: msg.recursion_available(),
+            authentic_data: msg.authentic_data(),
+        }
+    }
+}
+
+/// The prefix lengths of the client subnet option echoed by a server, see RFC 7871 section 7.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct EcsScope {
+    /// The source prefix length of the subnet sent in the query
+    pub source_prefix: u8,
+    /// The prefix length of the networks the answer is valid for, 0 if the answer does not
+    /// depend on the client subnet
+    pub scope_prefix: u8,
+}
+
+impl EcsScope {
+    /// Read the client subnet option of a response
+    fn from_response(msg: &Message) -> Option<Self> {
+        let Some(EdnsOption::Subnet(subnet)) = msg.extensions().as_ref()?.option(EdnsCode::Subnet)
+        else {
+            return None;
+        };
+        // `ClientSubnet` has no getters, the prefix lengths follow the 2 octets of the family
+        let bytes = Vec::<u8>::try_from(subnet).ok()?;
+        Some(Self {
+            source_prefix: bytes[2],
+            scope_prefix: bytes[3],
+        })
+    }
+}
+
+impl DnsAnswer {
+    /// Get the TTL of the address record of an IP
+    pub fn ttl(&self, ip: IpAddr) -> Option<u32> {
+        let ip = ip.to_string();
+        self.records
+            .iter()
+            .find(|record| record.record_type != "CNAME" && record.data == ip)
+            .map(|record| record.ttl)
+    }
+
+    /// Follow the CNAME chain starting at `name` through `records`, collecting the addresses of
+    /// the last name in the chain.
+    ///
+    /// Returns the last name in the chain.
+    fn follow(&mut self, mut name: Name, records: &[Record]) -> Name {
+        while self.cname_chain.len() < MAX_CNAME_CHAIN {
+            let target = records.iter().find_map(|record| match record.data() {
+                Some(RData::CNAME(target)) if record.name() == &name => {
+                    Some((record, target.0.clone()))
+                }
+                _ => None,
+            });
+
+            match target {
+                Some((record, target)) => {
+                    self.records
+                        .push(DnsRecord::new(record, display_name(&target)));
+                    self.cname_chain.push(display_name(&target));
+                    name = target;
+                }
+                None => break,
+            }
+        }
+
+        for record in records.iter().filter(|record| record.name() == &name) {
+            let ip = match record.data() {
+                Some(RData::A(address)) => IpAddr::from(address.0),
+                Some(RData::AAAA(address)) => IpAddr::from(address.0),
+                _ => continue,
+            };
+            self.records.push(DnsRecord::new(record, ip.to_string()));
+            self.ips.push(ip);
+        }
+
+        name
+    }
+}
+
+/// Format a name without the trailing root label
+fn display_name(name: &Name) -> String {
+    name.to_string().trim_end_matches('.').to_string()
+}
+
+impl Debug for DnsClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DnsClient")
+    }
+}
+
+/// A DNS resolver
+#[derive(Clone, Debug)]
+pub enum DnsResolver {
+    Google,
+    CloudFlare,
+    Quad9,
+    Custom(SocketAddr),
+    Tcp(SocketAddr),
+    #[cfg(feature = "dns-over-tls")]
+    Tls(DnsTlsServer),
+    #[cfg(feature = "dns-over-https")]
+    Https(DnsHttpsServer),
+}
+
+impl DnsResolver {
+    /// Get the address of the DNS server
+    pub fn server_addr(&self) -> DnsServerAddr {
+        match self {
+            DnsResolver::Google => DnsServerAddr::Google,
+            DnsResolver::CloudFlare => DnsServerAddr::CloudFlare,
+            DnsResolver::Quad9 => DnsServerAddr::Quad9,
+            DnsResolver::Custom(addr) => DnsServerAddr::Custom(*addr),
+            DnsResolver::Tcp(addr) => DnsServerAddr::Tcp(*addr),
+            #[cfg(feature = "dns-over-tls")]
+            DnsResolver::Tls(server) => DnsServerAddr::Tls(server.clone()),
+            #[cfg(feature = "dns-over-https")]
+            DnsResolver::Https(server) => DnsServerAddr::Https(server.clone()),
+        }
+    }
+
+    /// Connect to the DNS server
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client fails to connect, see [`DnsResolver::try_connect`].
+    pub async fn connect(&self) -> DnsClient {
+        DnsClient::new(self.server_addr()).await
+    }
+
+    /// Connect to the DNS server, failing if it cannot be reached
+    pub async fn try_connect(&self) -> Result<DnsClient> {
+        DnsClient::try_new(self.server_addr()).await
+    }
+
+    /// Connect to the DNS server with the given timeouts and retries, failing if it cannot be
+    /// reached
+    pub async fn try_connect_with_options(&self, options: DnsQueryOptions) -> Result<DnsClient> {
+        DnsClient::try_new_with_options(self.server_addr(), options).await
+    }
+}
+
+impl FromStr for DnsResolver {
+    type Err = Error;
+
+    /// Parse a resolver from `google`, `cloudflare`, `quad9`, an IP or an IP and port
+    ///
+    /// Any of these prefixed with `tcp://` is queried over TCP only, otherwise queries are sent
+    /// over UDP and retried over TCP when the response is truncated. With the `dns-over-tls`
+    /// feature, `tls://<ip or host>[:port][#server name]` is parsed as a DNS-over-TLS server, the
+    /// port defaults to 853 and the server name to the IP or the host. With the `dns-over-https`
+    /// feature, an `https://` URL is parsed as a DNS-over-HTTPS server sending POST requests, or
+    /// GET requests with the `https+get://` scheme.
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(server) = s.strip_prefix("tcp://") {
+            return match server.parse()? {
+                resolver @ (DnsResolver::Google
+                | DnsResolver::CloudFlare
+                | DnsResolver::Quad9
+                | DnsResolver::Custom(_)) => resolver
+                    .server_addr()
+                    .addr()
+                    .map(DnsResolver::Tcp)
+                    .ok_or(Error::InvalidResolver(s.to_string())),
+                _ => Err(Error::InvalidResolver(s.to_string())),
+            };
+        }
+        #[cfg(feature = "dns-over-tls")]
+        if let Some(server) = s.strip_prefix("tls://") {
+            return server.parse().map(DnsResolver::Tls);
+        }
+        #[cfg(feature = "dns-over-https")]
+        if s.starts_with("https://") || s.starts_with("https+get://") {
+            return s.parse().map(DnsResolver::Https);
+        }
+
+        match s.to_ascii_lowercase().as_str() {
+            "google" => Ok(DnsResolver::Google),
+            "cloudflare" => Ok(DnsResolver::CloudFlare),
+            "quad9" => Ok(DnsResolver::Quad9),
+            _ => parse_socket_addr(s, 53)
+                .map(DnsResolver::Custom)
+                .ok_or(Error::InvalidResolver(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for DnsResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsResolver::Google => write!(f, "google"),
+            DnsResolver::CloudFlare => write!(f, "cloudflare"),
+            DnsResolver::Quad9 => write!(f, "quad9"),
+            DnsResolver::Custom(addr) => write!(f, "{}", addr),
+            DnsResolver::Tcp(addr) => write!(f, "tcp://{}", addr),
+            #[cfg(feature = "dns-over-tls")]
+            DnsResolver::Tls(server) => write!(f, "tls://{}", server),
+            #[cfg(feature = "dns-over-https")]
+            DnsResolver::Https(server) => write!(f, "{}", server),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsResolver {
+    /// Deserialize a resolver from its string form, see [`DnsResolver::from_str`]
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parse a domain name
+pub(crate) fn parse_name(domain: &str) -> Result<Name> {
+    Name::from_ascii(domain).map_err(|e| Error::InvalidName {
+        name: domain.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Parse a client subnet from an IPv4 or IPv6 CIDR
+pub(crate) fn parse_subnet(subnet: &str) -> Result<ClientSubnet> {
+    subnet
+        .parse()
+        .map_err(|_| Error::InvalidSubnet(subnet.to_string()))
+}
+
+/// Parse an IP with an optional port, using `default_port` if there is none
+pub(crate) fn parse_socket_addr(s: &str, default_port: u16) -> Option<SocketAddr> {
+    s.parse::<SocketAddr>()
+        .or_else(|_| {
+            s.parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, default_port))
+        })
+        .ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use hickory_proto::rr::rdata;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4};
+
+    /// Spawn a local UDP DNS server which replies to every query with `answer`
+    pub(crate) async fn spawn_udp_server<F>(answer: F) -> SocketAddr
+    where
+        F: Fn(&Message) -> Message + Send + 'static,
+    {
+        spawn_lossy_udp_server(0, answer).await
+    }
+
+    /// Spawn a local UDP DNS server which ignores the first `dropped` queries and replies to
+    /// the next ones with `answer`
+    pub(crate) async fn spawn_lossy_udp_server<F>(dropped: usize, answer: F) -> SocketAddr
+    where
+        F: Fn(&Message) -> Message + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 4096];
+            let mut received = 0;
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                received += 1;
+                if received <= dropped {
+                    continue;
+                }
+                let request = Message::from_vec(&buf[..len]).unwrap();
+                let response = answer(&request).to_vec().unwrap();
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Spawn a local TCP DNS server on `addr` which replies to every query with `answer`
+    ///
+    /// Binding port 0 picks a free port, the bound address is returned.
+    pub(crate) async fn spawn_tcp_server<F>(addr: SocketAddr, answer: F) -> SocketAddr
+    where
+        F: Fn(&Message) -> Message + Send + Sync + 'static,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let answer = Arc::new(answer);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let answer = answer.clone();
+                tokio::spawn(async move {
+                    while let Ok(len) = stream.read_u16().await {
+                        let mut buf = vec![0u8; len as usize];
+                        stream.read_exact(&mut buf).await.unwrap();
+                        let request = Message::from_vec(&buf).unwrap();
+                        let response = answer(&request).to_vec().unwrap();
+                        stream.write_u16(response.len() as u16).await.unwrap();
+                        stream.write_all(&response).await.unwrap();
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    /// Build a response to `request` carrying the given answer records
+    pub(crate) fn response_with(request: &Message, answers: Vec<RData>) -> Message {
+        let name = request.queries()[0].name().clone();
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(OpCode::Query)
+            .add_queries(request.queries().to_vec())
+            .add_answers(
+                answers
+                    .into_iter()
+                    .map(|data| Record::from_rdata(name.clone(), 60, data)),
+            );
+        response
+    }
+
+    /// Answer A queries with 192.0.2.1 and AAAA queries with 2001:db8::1
+    pub(crate) fn address_answer(request: &Message) -> Message {
+        let data = match request.queries()[0].query_type() {
+            RecordType::AAAA => {
+                RData::AAAA(rdata::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)))
+            }
+            _ => RData::A(rdata::A(Ipv4Addr::new(192, 0, 2, 1))),
+        };
+        response_with(request, vec![data])
+    }
+
+    #[tokio::test]
+    async fn test_dns_server_addr_google() {
+        let google = DnsServerAddr::Google.addr().unwrap();
+        assert_eq!(
+            google,
+            ("8.8.8.8", 53).to_socket_addrs().unwrap().next().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dns_server_addr_cloudflare() {
+        let cloudflare = DnsServerAddr::CloudFlare.addr().unwrap();
+        assert_eq!(
+            cloudflare,
+            ("1.1.1.1", 53).to_socket_addrs().unwrap().next().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dns_server_addr_custom() {
+        let custom_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 1), 8080));
+        let custom = DnsServerAddr::Custom(custom_addr).addr();
+        assert_eq!(custom, Some(custom_addr));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_valid() {
+        let resolver = DnsResolver::Google;
+        let client = resolver.connect().await;
+        let result = client
+            .resolve_with_subnet("example.com", "24.24.24.24/24")
+            .await;
+        assert!(result.is_ok());
+        assert!(!result.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_invalid_subnet() {
+        let resolver = DnsResolver::Google;
+        let client = resolver.connect().await;
+        let result = client
+            .resolve_with_subnet("example.com", "not_a_subnet")
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_and_type_aaaa() {
+        let addr = spawn_udp_server(address_answer).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "2001:db8::/48", DnsRecordType::AAAA)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["2001:db8::1".parse::<IpAddr>().unwrap()]);
+        assert!(result.cname_chain.is_empty());
+        assert_eq!(result.transport, DnsTransport::Udp);
+        assert_eq!(result.attempts, 1);
+    }
+
+    /// Options retrying quickly, for servers dropping queries
+    fn fast_retries(retries: u32) -> DnsQueryOptions {
+        DnsQueryOptions {
+            timeout: Duration::from_millis(100),
+            retries,
+            backoff: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_retries_lost_queries() {
+        let addr = spawn_lossy_udp_server(2, address_answer).await;
+        let client = DnsResolver::Custom(addr)
+            .try_connect_with_options(fast_retries(2))
+            .await
+            .unwrap();
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(result.attempts, 3);
+        // Two timeouts and the backoffs before both retries
+        assert!(result.latency >= Duration::from_millis(230));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_gives_up_after_retries() {
+        let addr = spawn_lossy_udp_server(usize::MAX, address_answer).await;
+        let client = DnsResolver::Custom(addr)
+            .try_connect_with_options(fast_retries(1))
+            .await
+            .unwrap();
+        let result = client
+            .resolve_with_subnet("example.com", "24.24.24.0/24")
+            .await;
+        assert_eq!(result, Err(Error::DnsTimeout("example.com".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_detailed_reports_attempts_on_timeout() {
+        let addr = spawn_lossy_udp_server(usize::MAX, address_answer).await;
+        let options = fast_retries(2);
+        let client = DnsResolver::Custom(addr)
+            .try_connect_with_options(options)
+            .await
+            .unwrap();
+        let failure = client
+            .resolve_detailed("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap_err();
+        assert_eq!(failure.error, Error::DnsTimeout("example.com".to_string()));
+        assert_eq!(failure.attempts, options.retries + 1);
+        // Three timeouts and the backoffs before both retries
+        assert!(failure.latency >= Duration::from_millis(330));
+    }
+
+    #[test]
+    fn test_dns_query_options_backoff() {
+        let options = fast_retries(3);
+        assert_eq!(options.backoff(0), Duration::from_millis(10));
+        assert_eq!(options.backoff(2), Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_truncated_falls_back_to_tcp() {
+        let addr = spawn_udp_server(|request| {
+            let mut response = response_with(request, vec![]);
+            response.set_truncated(true);
+            response
+        })
+        .await;
+        spawn_tcp_server(addr, address_answer).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(result.transport, DnsTransport::Tcp);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_forced_tcp() {
+        let addr = spawn_tcp_server("127.0.0.1:0".parse().unwrap(), address_answer).await;
+        let client = DnsResolver::Tcp(addr).connect().await;
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "2001:db8::/48", DnsRecordType::AAAA)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["2001:db8::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(result.transport, DnsTransport::Tcp);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_local_a() {
+        let addr = spawn_udp_server(address_answer).await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client
+            .resolve_with_subnet("example.com", "24.24.24.0/24")
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_ecs_scope() {
+        let addr = spawn_udp_server(|request| {
+            let mut response = address_answer(request);
+            if request.queries()[0]
+                .name()
+                .to_string()
+                .starts_with("scoped")
+            {
+                let mut edns = Edns::new();
+                edns.options_mut()
+                    .insert(EdnsOption::Subnet(ClientSubnet::new(
+                        Ipv4Addr::new(24, 24, 24, 0).into(),
+                        24,
+                        20,
+                    )));
+                response.set_edns(edns);
+            }
+            response
+        })
+        .await;
+        let client = DnsResolver::Custom(addr).connect().await;
+
+        let result = client
+            .resolve_with_subnet_and_type("scoped.example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(
+            result.ecs,
+            Some(EcsScope {
+                source_prefix: 24,
+                scope_prefix: 20
+            })
+        );
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ecs, None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_cname_chain() {
+        let addr = spawn_udp_server(|request| {
+            let cname = |owner: &str, target: &str| {
+                Record::from_rdata(
+                    Name::from_ascii(owner).unwrap(),
+                    60,
+                    RData::CNAME(rdata::CNAME(Name::from_ascii(target).unwrap())),
+                )
+            };
+            let mut response = response_with(request, vec![]);
+            response.add_answers(vec![
+                cname("www.example.com.", "www.example.com.cdn.net."),
+                cname("www.example.com.cdn.net.", "edge.tm.net."),
+                Record::from_rdata(
+                    Name::from_ascii("edge.tm.net.").unwrap(),
+                    60,
+                    RData::A(rdata::A(Ipv4Addr::new(192, 0, 2, 7))),
+                ),
+            ]);
+            response
+        })
+        .await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client
+            .resolve_with_subnet_and_type("www.example.com.", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["192.0.2.7".parse::<IpAddr>().unwrap()]);
+        assert_eq!(
+            result.cname_chain,
+            vec![
+                "www.example.com.cdn.net".to_string(),
+                "edge.tm.net".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_answer_metadata() {
+        let addr = spawn_udp_server(|request| {
+            let mut response = response_with(request, vec![]);
+            response
+                .set_authoritative(true)
+                .set_recursion_available(true)
+                .add_answers(vec![
+                    Record::from_rdata(
+                        Name::from_ascii("www.example.com.").unwrap(),
+                        3600,
+                        RData::CNAME(rdata::CNAME(Name::from_ascii("geo.example.net.").unwrap())),
+                    ),
+                    Record::from_rdata(
+                        Name::from_ascii("geo.example.net.").unwrap(),
+                        30,
+                        RData::A(rdata::A(Ipv4Addr::new(192, 0, 2, 7))),
+                    ),
+                ]);
+            response
+        })
+        .await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client
+            .resolve_with_subnet_and_type("www.example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.records,
+            vec![
+                DnsRecord {
+                    name: "www.example.com".to_string(),
+                    record_type: "CNAME".to_string(),
+                    data: "geo.example.net".to_string(),
+                    ttl: 3600,
+                },
+                DnsRecord {
+                    name: "geo.example.net".to_string(),
+                    record_type: "A".to_string(),
+                    data: "192.0.2.7".to_string(),
+                    ttl: 30,
+                },
+            ]
+        );
+        assert_eq!(result.ttl("192.0.2.7".parse().unwrap()), Some(30));
+        assert_eq!(result.ttl("192.0.2.8".parse().unwrap()), None);
+        assert_eq!(result.rcode, "NOERROR");
+        assert_eq!(
+            result.flags,
+            DnsFlags {
+                authoritative: true,
+                recursion_available: true,
+                authentic_data: false,
+            }
+        );
+        assert_eq!(result.server, Some(addr));
+        assert!(result.response_time <= result.latency);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_cname_requery() {
+        let addr = spawn_udp_server(|request| {
+            let name = request.queries()[0].name().clone();
+            if name == Name::from_ascii("www.example.com.").unwrap() {
+                response_with(
+                    request,
+                    vec![RData::CNAME(rdata::CNAME(
+                        Name::from_ascii("edge.tm.net.").unwrap(),
+                    ))],
+                )
+            } else {
+                address_answer(request)
+            }
+        })
+        .await;
+        let client = DnsResolver::Custom(addr).connect().await;
+        let result = client
+            .resolve_with_subnet_and_type("www.example.com.", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(result.cname_chain, vec!["edge.tm.net".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_error_rcodes() {
+        let addr = spawn_udp_server(|request| {
+            let mut response = response_with(request, vec![]);
+            if request.queries()[0]
+                .name()
+                .to_string()
+                .starts_with("missing")
+            {
+                response.set_response_code(ResponseCode::NXDomain);
+            } else if request.queries()[0]
+                .name()
+                .to_string()
+                .starts_with("private")
+            {
+                response.set_response_code(ResponseCode::Refused);
+            } else {
+                response.set_response_code(ResponseCode::ServFail);
+            }
+            response
+        })
+        .await;
+        let client = DnsResolver::Custom(addr).connect().await;
+
+        let result = client.resolve_with_subnet("missing.example.com", "24.24.24.0/24");
+        assert_eq!(
+            result.await,
+            Err(Error::NxDomain("missing.example.com".to_string()))
+        );
+        let result = client.resolve_with_subnet("broken.example.com", "24.24.24.0/24");
+        assert_eq!(
+            result.await,
+            Err(Error::ServFail("broken.example.com".to_string()))
+        );
+        let result = client.resolve_with_subnet("private.example.com", "24.24.24.0/24");
+        assert_eq!(
+            result.await,
+            Err(Error::Rcode {
+                name: "private.example.com".to_string(),
+                rcode: "REFUSED".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_dns_resolver_from_str() {
+        assert!(matches!("google".parse(), Ok(DnsResolver::Google)));
+        assert!(matches!("CloudFlare".parse(), Ok(DnsResolver::CloudFlare)));
+        assert!(matches!("quad9".parse(), Ok(DnsResolver::Quad9)));
+        assert_eq!(DnsServerAddr::Quad9.addr(), "9.9.9.11:53".parse().ok());
+        let custom: DnsResolver = "9.9.9.9".parse().unwrap();
+        assert_eq!(custom.to_string(), "9.9.9.9:53");
+        let custom: DnsResolver = "[2620:fe::fe]:5353".parse().unwrap();
+        assert_eq!(custom.to_string(), "[2620:fe::fe]:5353");
+        let tcp: DnsResolver = "tcp://9.9.9.9".parse().unwrap();
+        assert_eq!(tcp.to_string(), "tcp://9.9.9.9:53");
+        let tcp: DnsResolver = "tcp://google".parse().unwrap();
+        assert_eq!(tcp.to_string(), "tcp://8.8.8.8:53");
+        assert!("tcp://tcp://9.9.9.9".parse::<DnsResolver>().is_err());
+        assert!("not a resolver".parse::<DnsResolver>().is_err());
+    }
+}
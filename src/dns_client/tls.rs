@@ -0,0 +1,308 @@
+use std::{
+    fmt,
+    fs::File,
+    io::BufReader,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use hickory_client::client::AsyncClient;
+use hickory_proto::{iocompat::AsyncIoTokioAsStd, rustls::tls_client_connect};
+use rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore};
+use tokio::net::{lookup_host, TcpStream};
+
+use super::parse_socket_addr;
+use crate::error::{Error, Result};
+
+/// The default port of DNS-over-TLS servers
+pub const DEFAULT_TLS_PORT: u16 = 853;
+
+/// A DNS-over-TLS server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsTlsServer {
+    /// The IP or the host name of the server, the host name is resolved when connecting
+    pub host: String,
+    /// The port of the server
+    pub port: u16,
+    /// The name the server certificate is verified against, also sent as SNI
+    pub server_name: String,
+    /// A PEM file with the CA certificates to trust instead of the public web roots
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl DnsTlsServer {
+    /// Create a new DNS-over-TLS server trusting the public web roots
+    pub fn new(addr: SocketAddr, server_name: &str) -> Self {
+        Self {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+            server_name: server_name.to_string(),
+            ca_cert: None,
+        }
+    }
+
+    /// Create a new DNS-over-TLS server by host name, verifying its certificate against the
+    /// host name
+    pub fn with_host(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            server_name: host.to_string(),
+            ca_cert: None,
+        }
+    }
+
+    /// Get the address of the server if its host is an IP, host names are only resolved when
+    /// connecting
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.host
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, self.port))
+    }
+
+    /// Trust the CA certificates in the PEM file instead of the public web roots
+    pub fn with_ca_cert<T: Into<PathBuf>>(mut self, ca_cert: T) -> Self {
+        self.ca_cert = Some(ca_cert.into());
+        self
+    }
+
+    /// Build the TLS client config, loading the custom CA certificates if any
+    fn client_config(&self) -> Result<ClientConfig> {
+        let mut root_store = RootCertStore::empty();
+        match &self.ca_cert {
+            Some(path) => {
+                let file = File::open(path).map_err(|e| self.connect_error(e))?;
+                let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+                    .map_err(|e| self.connect_error(e))?;
+                let (added, _) = root_store.add_parsable_certificates(&certs);
+                if added == 0 {
+                    return Err(self
+                        .connect_error(format!("no CA certificate found in {}", path.display())));
+                }
+            }
+            None => root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            })),
+        }
+
+        Ok(ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth())
+    }
+
+    /// Connect to the server and complete the TLS handshake, waiting up to `timeout` for every
+    /// response
+    ///
+    /// Returns the client and the address of the server, the first address of its host name.
+    pub(crate) async fn connect(&self, timeout: Duration) -> Result<(AsyncClient, SocketAddr)> {
+        let addr = match self.addr() {
+            Some(addr) => addr,
+            None => lookup_host((self.host.as_str(), self.port))
+                .await
+                .map_err(|e| self.connect_error(e))?
+                .next()
+                .ok_or(self.connect_error(format!("no address found for {}", self.host)))?,
+        };
+        let (stream, handle) = tls_client_connect::<AsyncIoTokioAsStd<TcpStream>>(
+            addr,
+            self.server_name.clone(),
+            Arc::new(self.client_config()?),
+        );
+        let (client, bg) = AsyncClient::with_timeout(stream, handle, timeout, None)
+            .await
+            .map_err(|e| self.connect_error(e))?;
+        tokio::spawn(bg);
+
+        Ok((client, addr))
+    }
+
+    fn connect_error<E: ToString>(&self, e: E) -> Error {
+        Error::Connect {
+            resolver: format!("tls://{}", self),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DnsTlsServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr() {
+            Some(addr) => write!(f, "{}#{}", addr, self.server_name),
+            None => write!(f, "{}:{}#{}", self.host, self.port, self.server_name),
+        }
+    }
+}
+
+impl FromStr for DnsTlsServer {
+    type Err = Error;
+
+    /// Parse a server from `<ip or host>[:port][#server name]`
+    ///
+    /// The port defaults to 853 and the server name to the IP or the host.
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, server_name) = match s.split_once('#') {
+            Some((addr, server_name)) => (addr, Some(server_name)),
+            None => (s, None),
+        };
+        let server = match parse_socket_addr(addr, DEFAULT_TLS_PORT) {
+            Some(addr) => Self::new(addr, &addr.ip().to_string()),
+            None => {
+                let (host, port) = match addr.rsplit_once(':') {
+                    Some((host, port)) => (host, port.parse().ok()),
+                    None => (addr, Some(DEFAULT_TLS_PORT)),
+                };
+                match port {
+                    Some(port) if is_host_name(host) => Self::with_host(host, port),
+                    _ => return Err(Error::InvalidResolver(format!("tls://{}", s))),
+                }
+            }
+        };
+
+        Ok(match server_name {
+            Some(server_name) => Self {
+                server_name: server_name.to_string(),
+                ..server
+            },
+            None => server,
+        })
+    }
+}
+
+/// Check if a string is a host name, dot-separated labels of letters, digits and hyphens
+fn is_host_name(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_client::tests::address_answer;
+    use crate::dns_client::{DnsRecordType, DnsResolver};
+    use hickory_proto::op::Message;
+    use rustls::Certificate;
+    use std::net::IpAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    /// Spawn a local DNS-over-TLS server for `localhost` answering with [`address_answer`]
+    ///
+    /// Returns the address of the server and the path to its self-signed certificate.
+    async fn spawn_tls_server(name: &str) -> (SocketAddr, PathBuf) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_path = std::env::temp_dir().join(format!("dns-geo-checker-{}.pem", name));
+        std::fs::write(&ca_path, cert.serialize_pem().unwrap()).unwrap();
+
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(cert.serialize_der().unwrap())],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                tokio::spawn(async move {
+                    while let Ok(len) = stream.read_u16().await {
+                        let mut buf = vec![0; len as usize];
+                        stream.read_exact(&mut buf).await.unwrap();
+                        let request = Message::from_vec(&buf).unwrap();
+                        let response = address_answer(&request).to_vec().unwrap();
+                        stream.write_u16(response.len() as u16).await.unwrap();
+                        stream.write_all(&response).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        (addr, ca_path)
+    }
+
+    #[test]
+    fn test_dns_tls_server_from_str() {
+        let server: DnsTlsServer = "1.1.1.1#cloudflare-dns.com".parse().unwrap();
+        assert_eq!(
+            server,
+            DnsTlsServer::new("1.1.1.1:853".parse().unwrap(), "cloudflare-dns.com")
+        );
+        let server: DnsTlsServer = "10.0.0.53:8853".parse().unwrap();
+        assert_eq!(server.addr(), "10.0.0.53:8853".parse().ok());
+        assert_eq!(server.server_name, "10.0.0.53");
+        let server: DnsTlsServer = "dns.example.com".parse().unwrap();
+        assert_eq!(server, DnsTlsServer::with_host("dns.example.com", 853));
+        assert_eq!(server.addr(), None);
+        let server: DnsTlsServer = "dns.example.com:8853#resolver.example.com".parse().unwrap();
+        assert_eq!(
+            (server.host.as_str(), server.port),
+            ("dns.example.com", 8853)
+        );
+        assert_eq!(server.server_name, "resolver.example.com");
+        assert!("dns.example.com:port".parse::<DnsTlsServer>().is_err());
+        assert!("dns example.com".parse::<DnsTlsServer>().is_err());
+        assert!("".parse::<DnsTlsServer>().is_err());
+    }
+
+    #[test]
+    fn test_dns_resolver_from_str_tls() {
+        let resolver: DnsResolver = "tls://8.8.8.8#dns.google".parse().unwrap();
+        assert_eq!(resolver.to_string(), "tls://8.8.8.8:853#dns.google");
+        let resolver: DnsResolver = "tls://dns.google".parse().unwrap();
+        assert_eq!(resolver.to_string(), "tls://dns.google:853#dns.google");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_over_tls() {
+        let (addr, ca_path) = spawn_tls_server("resolve").await;
+        let server = DnsTlsServer::new(addr, "localhost").with_ca_cert(&ca_path);
+        let client = DnsResolver::Tls(server).try_connect().await.unwrap();
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_subnet_over_tls_by_host_name() {
+        let (addr, ca_path) = spawn_tls_server("host-name").await;
+        let server = DnsTlsServer::with_host("localhost", addr.port()).with_ca_cert(&ca_path);
+        let client = DnsResolver::Tls(server).try_connect().await.unwrap();
+        let result = client
+            .resolve_with_subnet_and_type("example.com", "24.24.24.0/24", DnsRecordType::A)
+            .await
+            .unwrap();
+        assert_eq!(result.ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(result.server.map(|server| server.port()), Some(addr.port()));
+    }
+
+    #[tokio::test]
+    async fn test_connect_tls_untrusted_certificate() {
+        let (addr, _) = spawn_tls_server("untrusted").await;
+        let server = DnsTlsServer::new(addr, "localhost");
+        let result = DnsResolver::Tls(server).try_connect().await;
+        assert!(matches!(result, Err(Error::Connect { .. })));
+    }
+}
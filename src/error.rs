@@ -0,0 +1,240 @@
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use thiserror::Error;
+
+/// The errors that can occur while checking the geolocation of a domain
+///
+/// Errors are recorded per test case, so a failure on one domain or subnet does not abort the
+/// whole run.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    /// The domain name is not a valid DNS name
+    #[error("invalid domain name {name}: {message}")]
+    InvalidName { name: String, message: String },
+    /// The subnet is not a valid CIDR
+    #[error("invalid subnet {0}")]
+    InvalidSubnet(String),
+    /// The resolver is neither a known public resolver nor an address
+    #[error("invalid resolver {0}, expected google, cloudflare, quad9 or an IP address")]
+    InvalidResolver(String),
+    /// The resolver name is neither defined in the `resolvers` table nor a valid resolver
+    #[error("unknown resolver {0}, it is not defined in resolvers")]
+    UnknownResolver(String),
+    /// The record type is not an address record type
+    #[error("invalid record type {0}, expected A or AAAA")]
+    InvalidRecordType(String),
+    /// The IP geo provider is unknown
+    #[error("unknown IP geo provider {0}, expected mmdb or ip-api")]
+    UnknownProvider(String),
+    /// The IP geo provider is known but its feature is not enabled
+    #[error("IP geo provider {0} is not compiled in, enable its feature")]
+    ProviderNotCompiledIn(String),
+    /// The config format is not supported
+    #[error("unknown config format {0}, expected toml, yaml or json")]
+    UnknownConfigFormat(String),
+    /// The environment variable referenced in the config is not set and has no default
+    #[error("environment variable {0} is not set")]
+    UnsetVariable(String),
+    /// The environment variable reference is malformed, e.g. `${VAR` or `${}`
+    #[error("invalid environment variable reference {0}")]
+    InvalidVariable(String),
+    /// The config file could not be read
+    #[error("failed to read config {path}: {message}")]
+    ConfigRead { path: String, message: String },
+    /// The config is malformed or does not have the expected structure
+    #[error("{}", describe_parse_error(.path, .line, .column, .key, .message))]
+    ConfigParse {
+        /// The path of the config file, `None` for a config parsed from a string
+        path: Option<String>,
+        /// The line of the error, starting at 1
+        line: Option<usize>,
+        /// The column of the error, starting at 1
+        column: Option<usize>,
+        /// The path of the offending key, e.g. `domain[0].record_types[1]`
+        key: Option<String>,
+        message: String,
+    },
+    /// The config has problems, all found at once
+    #[error("invalid config, {} problem(s) found: {}", .0.len(), join_problems(.0))]
+    InvalidConfig(Vec<ConfigProblem>),
+    /// The client could not connect to the DNS server
+    #[error("failed to connect to {resolver}: {message}")]
+    Connect { resolver: String, message: String },
+    /// The DNS query timed out
+    #[error("DNS query for {0} timed out")]
+    DnsTimeout(String),
+    /// The domain does not exist
+    #[error("DNS query for {0} returned NXDOMAIN")]
+    NxDomain(String),
+    /// The DNS server failed to resolve the domain
+    #[error("DNS query for {0} returned SERVFAIL")]
+    ServFail(String),
+    /// The domain exists but has no records of the queried type
+    #[error("DNS query for {0} returned no records")]
+    NoData(String),
+    /// The DNS server responded with another error response code, e.g. `REFUSED`
+    #[error("DNS query for {name} returned {rcode}")]
+    Rcode { name: String, rcode: String },
+    /// The DNS query or its response could not be carried, e.g. a closed connection or an
+    /// HTTP server error
+    #[error("DNS query for {name} failed in transport: {message}")]
+    Transport { name: String, message: String },
+    /// Any other DNS failure, e.g. a malformed response
+    #[error("DNS query for {name} failed: {message}")]
+    Dns { name: String, message: String },
+    /// No authoritative nameservers were found for the zone of the domain
+    #[error("no authoritative nameservers found for {0}")]
+    NoNameservers(String),
+    /// The geo routing key is not defined in `test_subnets`
+    #[error("unknown geo {0}, it is not defined in test_subnets")]
+    UnknownGeo(String),
+    /// The IP geo provider failed to look up the IP
+    #[error("geo lookup failed: {0}")]
+    ProviderLookup(String),
+    /// The IP geo provider has no country data for the IP
+    #[error("no geo data for {ip}: {reason}")]
+    MissingGeoData { ip: String, reason: String },
+    /// The IP is served from a different country than expected
+    #[error("Expected: {}, Actual: {actual}", .expected.join("/"))]
+    GeoMismatch {
+        expected: Vec<String>,
+        actual: String,
+    },
+    /// The ECS scope prefix returned by the server is broader than required, so the answer is
+    /// not specific to the client subnet
+    #[error("ECS scope /{scope} returned for {subnet} is broader than the minimum /{min}")]
+    ScopeTooBroad { subnet: String, scope: u8, min: u8 },
+    /// The address record of the IP may be cached for longer than allowed, delaying failovers
+    #[error("TTL {ttl}s of {ip} is longer than the maximum {max}s")]
+    TtlTooLong { ip: String, ttl: u32, max: u32 },
+    /// The resolver strips or ignores the EDNS client subnet option, so the answer says nothing
+    /// about the geo routing of the subnet
+    #[error("inconclusive, {0} does not support the EDNS client subnet option")]
+    EcsUnsupported(String),
+}
+
+impl Error {
+    /// Check if the error is a failed assertion on the answer, a geo mismatch, a too broad ECS
+    /// scope or a too long TTL, rather than a failure to run the check
+    pub fn is_mismatch(&self) -> bool {
+        matches!(
+            self,
+            Error::GeoMismatch { .. } | Error::ScopeTooBroad { .. } | Error::TtlTooLong { .. }
+        )
+    }
+
+    /// Check if the error is a transport failure which may not happen again when retrying
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::Connect { .. } | Error::DnsTimeout(_) | Error::Transport { .. }
+        )
+    }
+
+    /// Check if the error means the test case ran but its outcome cannot be trusted
+    pub fn is_inconclusive(&self) -> bool {
+        matches!(self, Error::EcsUnsupported(_))
+    }
+
+    /// The response code of the DNS response behind the error, if the server responded
+    pub fn rcode(&self) -> Option<&str> {
+        match self {
+            Error::NxDomain(_) => Some("NXDOMAIN"),
+            Error::ServFail(_) => Some("SERVFAIL"),
+            Error::NoData(_) => Some("NOERROR"),
+            Error::Rcode { rcode, .. } => Some(rcode),
+            _ => None,
+        }
+    }
+
+    /// A stable, machine-readable name of the error variant
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::InvalidName { .. } => "invalid_name",
+            Error::InvalidSubnet(_) => "invalid_subnet",
+            Error::InvalidResolver(_) => "invalid_resolver",
+            Error::UnknownResolver(_) => "unknown_resolver",
+            Error::InvalidRecordType(_) => "invalid_record_type",
+            Error::UnknownProvider(_) => "unknown_provider",
+            Error::ProviderNotCompiledIn(_) => "provider_not_compiled_in",
+            Error::UnknownConfigFormat(_) => "unknown_config_format",
+            Error::UnsetVariable(_) => "unset_variable",
+            Error::InvalidVariable(_) => "invalid_variable",
+            Error::ConfigRead { .. } => "config_read",
+            Error::ConfigParse { .. } => "config_parse",
+            Error::InvalidConfig(_) => "invalid_config",
+            Error::Connect { .. } => "connect",
+            Error::DnsTimeout(_) => "dns_timeout",
+            Error::NxDomain(_) => "nx_domain",
+            Error::ServFail(_) => "serv_fail",
+            Error::NoData(_) => "no_data",
+            Error::Rcode { .. } => "rcode",
+            Error::Transport { .. } => "transport",
+            Error::Dns { .. } => "dns",
+            Error::NoNameservers(_) => "no_nameservers",
+            Error::UnknownGeo(_) => "unknown_geo",
+            Error::ProviderLookup(_) => "provider_lookup",
+            Error::MissingGeoData { .. } => "missing_geo_data",
+            Error::GeoMismatch { .. } => "geo_mismatch",
+            Error::ScopeTooBroad { .. } => "scope_too_broad",
+            Error::TtlTooLong { .. } => "ttl_too_long",
+            Error::EcsUnsupported(_) => "ecs_unsupported",
+        }
+    }
+}
+
+/// A problem found in a config
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{location}: {message}")]
+pub struct ConfigProblem {
+    /// The path of the offending value, e.g. `domain[0].geo_routing[1]`
+    pub location: String,
+    /// What is wrong with the value
+    pub message: String,
+}
+
+impl ConfigProblem {
+    pub fn new<L: ToString, M: ToString>(location: L, message: M) -> Self {
+        Self {
+            location: location.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+fn describe_parse_error(
+    path: &Option<String>,
+    line: &Option<usize>,
+    column: &Option<usize>,
+    key: &Option<String>,
+    message: &str,
+) -> String {
+    let mut location = path.clone().unwrap_or("config".to_string());
+    if let (Some(line), Some(column)) = (line, column) {
+        location = format!("{}:{}:{}", location, line, column);
+    }
+    match key {
+        Some(key) => format!("failed to parse {}, key {}: {}", location, key, message),
+        None => format!("failed to parse {}: {}", location, message),
+    }
+}
+
+fn join_problems(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Errors are serialized as their kind and message
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Error", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// A result with the crate error type
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,430 @@
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::{env, fmt};
+
+use crate::error::{Error, Result};
+
+/// Expand the environment variables referenced in a string
+///
+/// `${VAR}` is replaced by the value of `VAR` and fails if it is not set, `${VAR:-default}` is
+/// replaced by `default` if `VAR` is not set or empty. `$$` is a literal `$`.
+///
+/// # Examples
+///
+/// ```
+/// use dns_geolocation_checker::interpolation::interpolate;
+///
+/// std::env::set_var("DNS_GEO_EXAMPLE_DIR", "/srv/mmdb");
+/// assert_eq!(
+///     interpolate("${DNS_GEO_EXAMPLE_DIR}/GeoLite2-City.mmdb").unwrap(),
+///     "/srv/mmdb/GeoLite2-City.mmdb"
+/// );
+/// assert_eq!(interpolate("${DNS_GEO_EXAMPLE_UNSET:-google}").unwrap(), "google");
+/// assert!(interpolate("${DNS_GEO_EXAMPLE_UNSET}").is_err());
+/// ```
+pub fn interpolate(value: &str) -> Result<String> {
+    interpolate_with(value, |name| env::var(name).ok())
+}
+
+/// Expand the variables referenced in a string, looking their values up with `lookup`
+fn interpolate_with<F: Fn(&str) -> Option<String>>(value: &str, lookup: F) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let Some(reference) = rest.strip_prefix("${") else {
+            expanded.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = reference
+            .find('}')
+            .ok_or(Error::InvalidVariable(rest.to_string()))?;
+        let (name, default) = match reference[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&reference[..end], None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::InvalidVariable(rest[..end + 3].to_string()));
+        }
+
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => expanded.push_str(default),
+            (Some(value), _) => expanded.push_str(&value),
+            (None, Some(default)) => expanded.push_str(default),
+            (None, None) => return Err(Error::UnsetVariable(name.to_string())),
+        }
+        rest = &reference[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// A deserializer expanding the environment variables in every string value, see
+/// [`interpolate`]
+///
+/// The keys of maps are left as they are.
+pub(crate) struct Interpolated<D>(pub(crate) D);
+
+/// Forward the `deserialize_*` methods to the inner deserializer with an interpolating visitor
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> std::result::Result<V::Value, Self::Error> {
+                self.0.$method($($arg,)* Interpolated(visitor))
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Interpolated<D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+/// Forward the `visit_*` methods of primitive values to the inner visitor
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> std::result::Result<Self::Value, E> {
+                self.0.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Interpolated<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_bytes(&[u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+        if v.contains('$') {
+            self.0.visit_string(interpolate(v).map_err(E::custom)?)
+        } else {
+            self.0.visit_str(v)
+        }
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> std::result::Result<Self::Value, E> {
+        if v.contains('$') {
+            self.0.visit_string(interpolate(v).map_err(E::custom)?)
+        } else {
+            self.0.visit_borrowed_str(v)
+        }
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> std::result::Result<Self::Value, E> {
+        if v.contains('$') {
+            self.0.visit_string(interpolate(&v).map_err(E::custom)?)
+        } else {
+            self.0.visit_string(v)
+        }
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(
+        self,
+        v: &'de [u8],
+    ) -> std::result::Result<Self::Value, E> {
+        self.0.visit_borrowed_bytes(v)
+    }
+
+    fn visit_none<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+        self.0.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+        self.0.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        self.0.visit_some(Interpolated(deserializer))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        self.0.visit_newtype_struct(Interpolated(deserializer))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> std::result::Result<Self::Value, A::Error> {
+        self.0.visit_seq(Interpolated(seq))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> std::result::Result<Self::Value, A::Error> {
+        self.0.visit_map(Interpolated(map))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> std::result::Result<Self::Value, A::Error> {
+        self.0.visit_enum(Interpolated(data))
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Interpolated<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        self.0.deserialize(Interpolated(deserializer))
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Interpolated<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> std::result::Result<Option<T::Value>, Self::Error> {
+        self.0.next_element_seed(Interpolated(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Interpolated<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> std::result::Result<Option<K::Value>, Self::Error> {
+        self.0.next_key_seed(seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        self.0.next_value_seed(Interpolated(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for Interpolated<A> {
+    type Error = A::Error;
+    type Variant = Interpolated<A::Variant>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> std::result::Result<(V::Value, Self::Variant), Self::Error> {
+        let (value, variant) = self.0.variant_seed(Interpolated(seed))?;
+        Ok((value, Interpolated(variant)))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Interpolated<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> std::result::Result<(), Self::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> std::result::Result<T::Value, Self::Error> {
+        self.0.newtype_variant_seed(Interpolated(seed))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        self.0.tuple_variant(len, Interpolated(visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        self.0.struct_variant(fields, Interpolated(visitor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/dns".to_string()),
+            "EMPTY" => Some("".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate_with() {
+        assert_eq!(interpolate_with("google", lookup), Ok("google".to_string()));
+        assert_eq!(
+            interpolate_with("${HOME}/mmdb/${HOME}", lookup),
+            Ok("/home/dns/mmdb//home/dns".to_string())
+        );
+        assert_eq!(
+            interpolate_with("${MISSING:-./mmdb}/city.mmdb", lookup),
+            Ok("./mmdb/city.mmdb".to_string())
+        );
+        assert_eq!(
+            interpolate_with("${EMPTY:-fallback}", lookup),
+            Ok("fallback".to_string())
+        );
+        assert_eq!(interpolate_with("${EMPTY}", lookup), Ok("".to_string()));
+        assert_eq!(
+            interpolate_with("${HOME:-}", lookup),
+            Ok("/home/dns".to_string())
+        );
+        assert_eq!(
+            interpolate_with("$5 $$HOME $${HOME}", lookup),
+            Ok("$5 $HOME ${HOME}".to_string())
+        );
+        assert_eq!(
+            interpolate_with("${MISSING}", lookup),
+            Err(Error::UnsetVariable("MISSING".to_string()))
+        );
+        assert_eq!(
+            interpolate_with("a ${HOME", lookup),
+            Err(Error::InvalidVariable("${HOME".to_string()))
+        );
+        assert_eq!(
+            interpolate_with("${HO ME} b", lookup),
+            Err(Error::InvalidVariable("${HO ME}".to_string()))
+        );
+        assert_eq!(
+            interpolate_with("${:-x}", lookup),
+            Err(Error::InvalidVariable("${:-x}".to_string()))
+        );
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Transport {
+        Udp,
+        Tcp,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Test {
+        path: String,
+        transport: Transport,
+        tags: Vec<String>,
+        table: HashMap<String, Option<String>>,
+    }
+
+    #[test]
+    fn test_interpolated_deserializer() {
+        env::set_var("DNS_GEO_TEST_TRANSPORT", "tcp");
+        let contents = r#"{
+            "path": "$${HOME}",
+            "transport": "${DNS_GEO_TEST_TRANSPORT}",
+            "tags": ["a", "${DNS_GEO_TEST_UNSET:-b}"],
+            "table": { "${KEY}": "${DNS_GEO_TEST_TRANSPORT}" }
+        }"#;
+        let mut de = serde_json::Deserializer::from_str(contents);
+        let test = Test::deserialize(Interpolated(&mut de)).unwrap();
+
+        assert_eq!(
+            test,
+            Test {
+                path: "${HOME}".to_string(),
+                transport: Transport::Tcp,
+                tags: vec!["a".to_string(), "b".to_string()],
+                table: HashMap::from([("${KEY}".to_string(), Some("tcp".to_string()))]),
+            }
+        );
+
+        let mut de = serde_json::Deserializer::from_str(r#"["${DNS_GEO_TEST_UNSET}"]"#);
+        let result = Vec::<String>::deserialize(Interpolated(&mut de));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "environment variable DNS_GEO_TEST_UNSET is not set at line 1 column 24"
+        );
+    }
+}
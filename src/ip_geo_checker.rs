@@ -1,73 +1,223 @@
 #![allow(dead_code)]
 
-use serde::Deserialize;
-use std::net::IpAddr;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize, Serializer};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 
 use crate::configs_parser::{Config, DomainConfig};
-use crate::dns_client::DnsResolver;
-use crate::ip_geo_client::{GetGeoIpInfo, IpGeoClient, IpGeoProvider};
+use crate::dns_client::{
+    DnsAnswer, DnsClient, DnsFlags, DnsOutcome, DnsQueryOptions, DnsRecordType, DnsResolver,
+    DnsTransport, EcsScope, EcsSupport,
+};
+use crate::error::Error;
+use crate::ip_geo_client::{GetGeoIpInfo, IpGeoProvider};
 
 #[cfg(feature = "ip-api")]
 use crate::ip_geo_client::ip_api_client::IpApiClient;
 #[cfg(feature = "mmdb")]
 use crate::ip_geo_client::mmdb_client::MMDBClient;
+#[cfg(any(feature = "ip-api", feature = "mmdb"))]
+use crate::ip_geo_client::IpGeoClient;
 
 /// A struct to hold the response for the Geo IP API
-#[derive(Default, Debug, Clone, Deserialize)]
+///
+/// Providers only know some IPs at country level or not at all, so every field but the query
+/// is optional.
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct GeoIpResponse {
     pub query: String,
-    pub country: String,
-    #[serde(rename = "countryCode")]
-    pub country_code: String,
-    pub region: String,
-    #[serde(rename = "regionName")]
-    pub region_name: String,
-    pub city: String,
-    pub lat: f64,
-    pub lon: f64,
+    pub country: Option<String>,
+    #[serde(rename(deserialize = "countryCode"))]
+    pub country_code: Option<String>,
+    pub region: Option<String>,
+    #[serde(rename(deserialize = "regionName"))]
+    pub region_name: Option<String>,
+    pub city: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    /// The reason the provider has no data for the IP, e.g. it is not in the database
+    pub message: Option<String>,
+}
+
+impl GeoIpResponse {
+    /// Create a response for an IP the provider has no record of
+    pub fn not_found(ip: IpAddr) -> Self {
+        Self {
+            query: ip.to_string(),
+            message: Some("IP not found in database".to_string()),
+            ..Default::default()
+        }
+    }
 }
 
 /// A struct to hold the tested data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct IpGeoCheckerTestedData {
     /// The host of the domain
     pub host: String,
-    /// The IP address
-    pub ip: IpAddr,
+    /// The resolver the query was sent to, or the address of the authoritative nameserver
+    pub resolver: String,
+    /// The authoritative nameserver the query was sent to, `None` if it went to the resolver
+    pub nameserver: Option<String>,
+    /// The record type queried
+    pub record_type: DnsRecordType,
+    /// The canonical names the host resolved through, in order
+    pub cname_chain: Vec<String>,
+    /// The transport the answer was received over, `None` if the query failed
+    pub transport: Option<DnsTransport>,
+    /// The source prefix length of the client subnet echoed by the server, if any
+    pub source_prefix: Option<u8>,
+    /// The scope prefix length of the client subnet echoed by the server, if any
+    pub scope_prefix: Option<u8>,
+    /// The number of DNS messages sent to resolve the answer, including retries
+    pub attempts: u32,
+    /// The time taken to resolve the answer, serialized in milliseconds
+    #[serde(rename = "latency_ms", serialize_with = "serialize_millis")]
+    pub latency: Option<Duration>,
+    /// The time taken by the attempt which received the last response, serialized in
+    /// milliseconds
+    #[serde(rename = "response_time_ms", serialize_with = "serialize_millis")]
+    pub response_time: Option<Duration>,
+    /// The address of the server which answered, `None` if the query failed or was sent to a
+    /// DNS-over-HTTPS server
+    pub server: Option<SocketAddr>,
+    /// The response code of the last response, `None` if the query failed
+    pub rcode: Option<String>,
+    /// The header flags of the last response, `None` if the query failed
+    pub flags: Option<DnsFlags>,
+    /// The TTL of the address record of the IP in seconds
+    pub ttl: Option<u32>,
+    /// The IP address, `None` for test cases which failed before resolving one
+    pub ip: Option<IpAddr>,
     /// The response from the ip-api.com API
     pub geoip: GeoIpResponse,
+    /// The geo routing key of the subnet in `test_subnets`
+    pub geo: String,
     /// The subnet
     pub subnet: String,
-    /// The expected country code
-    pub expected: String,
+    /// The expected country codes, any of which may serve the geo, or the expected outcome such
+    /// as `nxdomain`
+    pub expected: Vec<String>,
     /// The actual country code
     pub actual: String,
-    /// The error message
-    pub error: Option<String>,
+    /// The error hit by the test case, if any
+    pub error: Option<Error>,
 }
 
 impl Default for IpGeoCheckerTestedData {
     fn default() -> Self {
         Self {
             host: "".to_string(),
-            ip: "0.0.0.0".parse().unwrap(),
+            resolver: "".to_string(),
+            nameserver: None,
+            record_type: DnsRecordType::A,
+            cname_chain: vec![],
+            transport: None,
+            source_prefix: None,
+            scope_prefix: None,
+            attempts: 0,
+            latency: None,
+            response_time: None,
+            server: None,
+            rcode: None,
+            flags: None,
+            ttl: None,
+            ip: None,
             geoip: GeoIpResponse::default(),
+            geo: "".to_string(),
             subnet: "".to_string(),
-            expected: "".to_string(),
+            expected: vec![],
             actual: "".to_string(),
             error: None,
         }
     }
 }
 
+fn serialize_millis<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration
+        .map(|duration| duration.as_secs_f64() * 1000.0)
+        .serialize(serializer)
+}
+
 impl IpGeoCheckerTestedData {
     pub fn set_host(&mut self, host: &str) -> &mut Self {
         self.host = host.to_string();
         self
     }
 
+    pub fn set_resolver<T: ToString>(&mut self, resolver: T) -> &mut Self {
+        self.resolver = resolver.to_string();
+        self
+    }
+
+    pub fn set_nameserver(&mut self, nameserver: &str) -> &mut Self {
+        self.nameserver = Some(nameserver.to_string());
+        self
+    }
+
+    pub fn set_record_type(&mut self, record_type: DnsRecordType) -> &mut Self {
+        self.record_type = record_type;
+        self
+    }
+
+    pub fn set_cname_chain(&mut self, cname_chain: &[String]) -> &mut Self {
+        self.cname_chain = cname_chain.to_vec();
+        self
+    }
+
+    pub fn set_transport(&mut self, transport: DnsTransport) -> &mut Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn set_ecs_scope(&mut self, ecs: EcsScope) -> &mut Self {
+        self.source_prefix = Some(ecs.source_prefix);
+        self.scope_prefix = Some(ecs.scope_prefix);
+        self
+    }
+
+    pub fn set_attempts(&mut self, attempts: u32) -> &mut Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn set_latency(&mut self, latency: Duration) -> &mut Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Set the metadata of the answer, shared by the IPs of the answer
+    pub fn set_answer(&mut self, answer: &DnsAnswer) -> &mut Self {
+        self.set_cname_chain(&answer.cname_chain)
+            .set_transport(answer.transport)
+            .set_attempts(answer.attempts)
+            .set_latency(answer.latency);
+        if let Some(ecs) = answer.ecs {
+            self.set_ecs_scope(ecs);
+        }
+        self.response_time = Some(answer.response_time);
+        self.server = answer.server;
+        self.rcode = Some(answer.rcode.clone());
+        self.flags = Some(answer.flags);
+        self
+    }
+
+    pub fn set_rcode(&mut self, rcode: &str) -> &mut Self {
+        self.rcode = Some(rcode.to_string());
+        self
+    }
+
+    pub fn set_ttl(&mut self, ttl: u32) -> &mut Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
     pub fn set_ip(&mut self, ip: IpAddr) -> &mut Self {
-        self.ip = ip;
+        self.ip = Some(ip);
         self
     }
 
@@ -76,13 +226,26 @@ impl IpGeoCheckerTestedData {
         self
     }
 
+    pub fn set_geo(&mut self, geo: &str) -> &mut Self {
+        self.geo = geo.to_string();
+        self
+    }
+
     pub fn set_subnet<T: ToString>(&mut self, subnet: T) -> &mut Self {
         self.subnet = subnet.to_string();
         self
     }
 
     pub fn set_expected(&mut self, expected: &str) -> &mut Self {
-        self.expected = expected.to_string().to_ascii_lowercase();
+        self.set_expected_any(&[expected])
+    }
+
+    /// Set the country codes or the outcomes expected, any of which passes the test case
+    pub fn set_expected_any<T: AsRef<str>>(&mut self, expected: &[T]) -> &mut Self {
+        self.expected = expected
+            .iter()
+            .map(|e| e.as_ref().to_ascii_lowercase())
+            .collect();
         self
     }
 
@@ -91,17 +254,24 @@ impl IpGeoCheckerTestedData {
         self
     }
 
-    /// Check if the expected country code matches the actual country code
+    pub fn set_error(&mut self, error: Error) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Check if the actual country code is one of the expected country codes
+    ///
+    /// An error already recorded on the test case is kept as is.
     pub fn test(&self) -> Self {
-        if self.expected == self.actual {
+        if self.is_err() || self.expected.contains(&self.actual) {
             self.clone()
         } else {
             let mut err_res = self.clone();
-            err_res.error = Some(format!(
-                "Expected: {}, Actual: {}",
-                self.expected, self.actual
-            ));
-            err_res.clone()
+            err_res.error = Some(Error::GeoMismatch {
+                expected: self.expected.clone(),
+                actual: self.actual.clone(),
+            });
+            err_res
         }
     }
 
@@ -113,11 +283,172 @@ impl IpGeoCheckerTestedData {
         self.error.is_none()
     }
 
-    pub fn err(&self) -> Option<String> {
+    /// Check if the test case ran but the geolocation did not match
+    pub fn is_mismatch(&self) -> bool {
+        self.error.as_ref().is_some_and(|e| e.is_mismatch())
+    }
+
+    /// Check if the test case ran but its outcome cannot be trusted
+    pub fn is_inconclusive(&self) -> bool {
+        self.error.as_ref().is_some_and(|e| e.is_inconclusive())
+    }
+
+    /// Check if the test case could not be checked
+    pub fn is_error(&self) -> bool {
+        self.is_err() && !self.is_mismatch() && !self.is_inconclusive()
+    }
+
+    pub fn err(&self) -> Option<Error> {
         self.error.clone()
     }
 }
 
+/// A summary of the outcome of a run
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IpGeoCheckerSummary {
+    /// The number of test cases
+    pub total: usize,
+    /// The number of test cases served from the expected country
+    pub matched: usize,
+    /// The number of test cases served from another country
+    pub mismatched: usize,
+    /// The number of test cases resolved by a resolver ignoring the client subnet
+    pub inconclusive: usize,
+    /// The number of test cases which could not be checked
+    pub errors: usize,
+}
+
+impl IpGeoCheckerSummary {
+    /// Summarize the tested data of a run
+    pub fn from_results(results: &[IpGeoCheckerTestedData]) -> Self {
+        Self {
+            total: results.len(),
+            matched: results.iter().filter(|r| r.is_ok()).count(),
+            mismatched: results.iter().filter(|r| r.is_mismatch()).count(),
+            inconclusive: results.iter().filter(|r| r.is_inconclusive()).count(),
+            errors: results.iter().filter(|r| r.is_error()).count(),
+        }
+    }
+
+    /// Summarize the tested data of a run per resolver, in order of first appearance
+    pub fn by_resolver(results: &[IpGeoCheckerTestedData]) -> Vec<IpGeoCheckerResolverSummary> {
+        let mut resolvers: Vec<&str> = vec![];
+        for result in results {
+            if !resolvers.contains(&result.resolver.as_str()) {
+                resolvers.push(&result.resolver);
+            }
+        }
+
+        resolvers
+            .into_iter()
+            .map(|resolver| {
+                let results: Vec<IpGeoCheckerTestedData> = results
+                    .iter()
+                    .filter(|r| r.resolver == resolver)
+                    .cloned()
+                    .collect();
+                IpGeoCheckerResolverSummary {
+                    resolver: resolver.to_string(),
+                    summary: Self::from_results(&results),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A summary of the outcome of a run for a single resolver
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IpGeoCheckerResolverSummary {
+    /// The resolver, or the address of the authoritative nameserver
+    pub resolver: String,
+    #[serde(flatten)]
+    pub summary: IpGeoCheckerSummary,
+}
+
+/// A test case the resolvers answered with different countries
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IpGeoCheckerDivergence {
+    pub host: String,
+    pub geo: String,
+    pub subnet: String,
+    pub record_type: DnsRecordType,
+    /// The answer of every resolver which got to a geolocation
+    pub answers: Vec<IpGeoCheckerResolverAnswer>,
+}
+
+/// The answer of a resolver to a test case
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IpGeoCheckerResolverAnswer {
+    pub resolver: String,
+    /// The sorted IPs of the answer
+    pub ips: Vec<IpAddr>,
+    /// The sorted countries of the IPs
+    pub countries: Vec<String>,
+}
+
+impl IpGeoCheckerDivergence {
+    /// Find the test cases the resolvers answered with different countries
+    ///
+    /// Test cases are grouped by host, geo, subnet and record type. Resolvers which failed to
+    /// resolve or locate the IPs are left out of the comparison.
+    pub fn from_results(results: &[IpGeoCheckerTestedData]) -> Vec<Self> {
+        let mut cases: Vec<Self> = vec![];
+        for result in results.iter().filter(|r| !r.actual.is_empty()) {
+            let case = match cases.iter_mut().find(|c| c.is_case_of(result)) {
+                Some(case) => case,
+                None => {
+                    cases.push(Self {
+                        host: result.host.clone(),
+                        geo: result.geo.clone(),
+                        subnet: result.subnet.clone(),
+                        record_type: result.record_type,
+                        answers: vec![],
+                    });
+                    cases.last_mut().unwrap()
+                }
+            };
+            let answer = match case
+                .answers
+                .iter_mut()
+                .find(|a| a.resolver == result.resolver)
+            {
+                Some(answer) => answer,
+                None => {
+                    case.answers.push(IpGeoCheckerResolverAnswer {
+                        resolver: result.resolver.clone(),
+                        ips: vec![],
+                        countries: vec![],
+                    });
+                    case.answers.last_mut().unwrap()
+                }
+            };
+            answer.ips.extend(result.ip);
+            answer.ips.sort();
+            answer.countries.push(result.actual.clone());
+            answer.countries.sort();
+            answer.countries.dedup();
+        }
+
+        cases
+            .into_iter()
+            .filter(|case| {
+                case.answers
+                    .windows(2)
+                    .any(|pair| pair[0].countries != pair[1].countries)
+            })
+            .collect()
+    }
+
+    fn is_case_of(&self, result: &IpGeoCheckerTestedData) -> bool {
+        self.host == result.host
+            && self.geo == result.geo
+            && self.subnet == result.subnet
+            && self.record_type == result.record_type
+    }
+}
+
+/// The result of a domain, no longer produced by the checker
+#[deprecated(note = "use the `IpGeoCheckerTestedData` returned by `IpGeoChecker::check`")]
 #[derive(Default, Clone, Debug)]
 pub struct IpGeoCheckerResult {
     pub domain: DomainConfig,
@@ -128,25 +459,40 @@ pub struct IpGeoCheckerResult {
 
 #[derive(Clone, Debug)]
 pub struct IpGeoCheckerBuilder {
-    dns_resolver: DnsResolver,
+    dns_resolvers: Vec<DnsResolver>,
     config: Config,
 }
 
+impl Default for IpGeoCheckerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IpGeoCheckerBuilder {
     pub fn new() -> Self {
         Self {
-            dns_resolver: DnsResolver::Google,
+            dns_resolvers: vec![DnsResolver::Google],
             config: Config::default(),
         }
     }
 
+    /// Set the config, also using its DNS resolvers if it has any
     pub fn config(&mut self, config: &Config) -> &mut Self {
         self.config = config.clone();
-        self
+        self.dns_resolvers(config.resolvers())
     }
 
     pub fn dns_resolver(&mut self, dns_resolver: DnsResolver) -> &mut Self {
-        self.dns_resolver = dns_resolver;
+        self.dns_resolvers = vec![dns_resolver];
+        self
+    }
+
+    /// Set the DNS resolvers every domain is checked against, ignored if empty
+    pub fn dns_resolvers(&mut self, dns_resolvers: Vec<DnsResolver>) -> &mut Self {
+        if !dns_resolvers.is_empty() {
+            self.dns_resolvers = dns_resolvers;
+        }
         self
     }
 
@@ -154,7 +500,7 @@ impl IpGeoCheckerBuilder {
     pub fn with_ip_api_client(&mut self) -> IpGeoChecker<IpApiClient> {
         IpGeoChecker {
             client: IpGeoClient::with_provider::<IpApiClient>(&self.config),
-            dns_resolver: self.dns_resolver.clone(),
+            dns_resolvers: self.dns_resolvers.clone(),
             config: self.config.clone(),
         }
     }
@@ -163,89 +509,985 @@ impl IpGeoCheckerBuilder {
     pub fn with_mmdb_client(&mut self) -> IpGeoChecker<MMDBClient> {
         IpGeoChecker {
             client: IpGeoClient::with_provider::<MMDBClient>(&self.config),
-            dns_resolver: self.dns_resolver.clone(),
+            dns_resolvers: self.dns_resolvers.clone(),
             config: self.config.clone(),
         }
     }
+
+    /// Build the checker with the MMDB client, failing if the database cannot be opened
+    #[cfg(feature = "mmdb")]
+    pub fn try_with_mmdb_client(&mut self) -> Result<IpGeoChecker<MMDBClient>, Error> {
+        Ok(IpGeoChecker {
+            client: IpGeoProvider::new(MMDBClient::try_new(&self.config)?),
+            dns_resolvers: self.dns_resolvers.clone(),
+            config: self.config.clone(),
+        })
+    }
+}
+
+/// A DNS server the queries of a domain are sent to
+#[derive(Clone, Debug)]
+struct ResolverTarget {
+    /// The resolver or the address of the nameserver
+    resolver: String,
+    /// The name of the authoritative nameserver, if any
+    nameserver: Option<String>,
+    /// The client connected to the server
+    client: Result<DnsClient, Error>,
+    /// The EDNS client subnet support of the server for the domain, if probed
+    ecs_support: Option<EcsSupport>,
+}
+
+impl ResolverTarget {
+    /// Connect to a resolver, reported as `resolver`
+    async fn connect(
+        resolver: String,
+        dns_resolver: &DnsResolver,
+        options: DnsQueryOptions,
+    ) -> Self {
+        Self {
+            resolver,
+            nameserver: None,
+            client: dns_resolver.try_connect_with_options(options).await,
+            ecs_support: None,
+        }
+    }
+
+    /// Probe the EDNS client subnet support of the server with the domain
+    ///
+    /// The support is left unknown if the probe fails, the checks of the domain will fail the
+    /// same way.
+    async fn probe_ecs_support(&mut self, domain: &DomainConfig) {
+        if let Ok(client) = &self.client {
+            let record_type = domain.record_types.first().copied().unwrap_or_default();
+            self.ecs_support = client
+                .probe_ecs_support(&domain.host, record_type)
+                .await
+                .ok();
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct IpGeoChecker<T> {
     client: IpGeoProvider<T>,
-    dns_resolver: DnsResolver,
+    /// The DNS resolvers every domain is checked against, never empty
+    dns_resolvers: Vec<DnsResolver>,
     config: Config,
 }
 
 impl<T: GetGeoIpInfo + Clone> IpGeoChecker<T> {
     /// Create a new instance of the IpGeoChecker
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> IpGeoCheckerBuilder {
         IpGeoCheckerBuilder::new()
     }
 
     /// Check the Geo IP of the domains
+    ///
+    /// Failures are recorded on the tested data of the affected test case, so the run always
+    /// covers every domain. Domains are checked once per DNS resolver, the ones selected by the
+    /// domain or the default ones, or once per nameserver for authoritative domains, whose
+    /// nameservers are found with the first resolver. At most `max_concurrent_queries` test
+    /// cases are resolved at once, the results are in the order of the config.
     pub async fn check(&self) -> Vec<IpGeoCheckerTestedData> {
-        let resolver = self.dns_resolver.connect().await;
-        let test_subnets = self.config.test_subnets.clone();
-        let domains = self
-            .config
-            .domain
-            .iter()
-            .map(|d| d.clone())
-            .collect::<Vec<DomainConfig>>();
+        let resolvers = futures::future::join_all(self.dns_resolvers.iter().map(|dns_resolver| {
+            ResolverTarget::connect(
+                dns_resolver.to_string(),
+                dns_resolver,
+                self.config.dns_query,
+            )
+        }))
+        .await;
+
+        let mut named_resolvers = vec![];
+        let mut selections = vec![];
+        for domain in self.config.domain.iter() {
+            selections.push(
+                self.select_resolvers(domain, &resolvers, &mut named_resolvers)
+                    .await,
+            );
+        }
+        // The nameservers and the client subnet support of every domain are found concurrently
+        let domain_targets = futures::future::join_all(
+            self.config
+                .domain
+                .iter()
+                .zip(selections)
+                .map(|(domain, selected)| self.domain_targets(domain, selected)),
+        )
+        .await;
 
+        let mut results = vec![];
         let mut tasks = vec![];
-        for domain in domains.into_iter() {
-            domain.geo_routing.into_iter().for_each(|geo| {
-                let subnets = test_subnets.get(&geo.to_string()).unwrap().subnets.clone();
-                subnets.into_iter().for_each(|subnet| {
-                    let host = domain.host.clone();
-                    let c_geo = geo.clone();
-                    let c_resolver = resolver.clone();
-                    let c_subnet = subnet.clone();
-
-                    tasks.push(async move {
-                        let ips = c_resolver
-                            .resolve_with_subnet(&host.to_string(), &c_subnet.clone())
-                            .await
-                            .unwrap();
-
-                        let geoip_results = self
-                            .client
-                            .batch_get_ip_info(&ips)
-                            .await
-                            .unwrap()
-                            .iter()
-                            .map(|ip| {
-                                IpGeoCheckerTestedData::default()
-                                    .set_host(&host.to_string())
-                                    .set_ip(ip.query.parse().unwrap())
-                                    .set_geoip(ip.clone())
-                                    .set_subnet(&c_subnet.clone())
-                                    .set_expected(c_geo.as_str())
-                                    .set_actual(ip.country_code.as_str())
-                                    .test()
-                            })
-                            .collect::<Vec<IpGeoCheckerTestedData>>();
-
-                        geoip_results
-                    });
-                });
-            });
+        for (domain, targets) in self.config.domain.iter().zip(domain_targets.iter()) {
+            for geo in domain.geo_routing.iter().map(|routing| routing.region()) {
+                let mut data = IpGeoCheckerTestedData::default();
+                data.set_host(&domain.host)
+                    .set_resolver(domain.resolvers.first().unwrap_or(&resolvers[0].resolver))
+                    .set_geo(geo)
+                    .set_expected_any(&domain.expected(geo));
+
+                let targets = match targets {
+                    Ok(targets) => targets,
+                    Err(e) => {
+                        results.push(data.set_error(e.clone()).clone());
+                        continue;
+                    }
+                };
+                let Some(routing) = self.config.test_subnets.get(geo) else {
+                    data.set_error(Error::UnknownGeo(geo.to_string()));
+                    results.extend(targets.iter().map(|target| {
+                        let mut data = data.clone();
+                        data.set_resolver(&target.resolver);
+                        if let Some(nameserver) = &target.nameserver {
+                            data.set_nameserver(nameserver);
+                        }
+                        data
+                    }));
+                    continue;
+                };
+
+                for target in targets.iter() {
+                    for subnet in routing.subnets.iter() {
+                        for record_type in domain.record_types.iter() {
+                            tasks.push(self.check_subnet(
+                                target,
+                                domain,
+                                geo,
+                                subnet,
+                                *record_type,
+                            ));
+                        }
+                    }
+                }
+            }
         }
 
-        futures::future::join_all(tasks)
-            .await
+        let checked: Vec<_> = futures::stream::iter(tasks)
+            .buffered(self.config.max_concurrent_queries().max(1))
+            .collect()
+            .await;
+        results.extend(checked.into_iter().flatten());
+        results
+    }
+
+    /// Get the resolvers a domain is checked against
+    ///
+    /// Domains without selected resolvers use the default ones. Selected resolvers are
+    /// connected once and kept in `named_resolvers` for the next domains.
+    async fn select_resolvers(
+        &self,
+        domain: &DomainConfig,
+        defaults: &[ResolverTarget],
+        named_resolvers: &mut Vec<ResolverTarget>,
+    ) -> Result<Vec<ResolverTarget>, Error> {
+        if domain.resolvers.is_empty() {
+            return Ok(defaults.to_vec());
+        }
+
+        let mut targets = vec![];
+        for name in domain.resolvers.iter() {
+            if let Some(target) = named_resolvers.iter().find(|t| t.resolver == *name) {
+                targets.push(target.clone());
+                continue;
+            }
+            let (dns_resolver, options) = self.config.named_resolver(name)?;
+            let target = ResolverTarget::connect(name.clone(), &dns_resolver, options).await;
+            named_resolvers.push(target.clone());
+            targets.push(target);
+        }
+
+        Ok(targets)
+    }
+
+    /// Get the servers a domain is checked against, the selected resolvers or the authoritative
+    /// nameservers found with the first of them, probing their client subnet support if enabled
+    async fn domain_targets(
+        &self,
+        domain: &DomainConfig,
+        selected: Result<Vec<ResolverTarget>, Error>,
+    ) -> Result<Vec<ResolverTarget>, Error> {
+        let mut targets = match selected {
+            Ok(selected) if domain.is_authoritative() => {
+                self.authoritative_targets(&selected[0].client, domain)
+                    .await?
+            }
+            selected => selected?,
+        };
+        if self.config.probe_ecs {
+            futures::future::join_all(
+                targets
+                    .iter_mut()
+                    .map(|target| target.probe_ecs_support(domain)),
+            )
+            .await;
+        }
+
+        Ok(targets)
+    }
+
+    /// Connect to the authoritative nameservers of a domain
+    ///
+    /// The nameservers are taken from the config or found with the DNS resolver, a nameserver
+    /// which cannot be reached is kept as a target failing every query.
+    async fn authoritative_targets(
+        &self,
+        resolver: &Result<DnsClient, Error>,
+        domain: &DomainConfig,
+    ) -> Result<Vec<ResolverTarget>, Error> {
+        let resolver = resolver.as_ref().map_err(Clone::clone)?;
+        let nameservers = if domain.nameservers.is_empty() {
+            resolver.find_nameservers(&domain.host).await?
+        } else {
+            domain.nameservers.clone()
+        };
+
+        let targets = nameservers.into_iter().map(|nameserver| async move {
+            match resolver.resolve_nameserver(&nameserver).await {
+                Ok(addr) => ResolverTarget {
+                    nameserver: Some(nameserver),
+                    ..ResolverTarget::connect(
+                        addr.to_string(),
+                        &DnsResolver::Custom(addr),
+                        self.config.dns_query,
+                    )
+                    .await
+                },
+                Err(e) => ResolverTarget {
+                    resolver: nameserver.clone(),
+                    nameserver: Some(nameserver),
+                    client: Err(e),
+                    ecs_support: None,
+                },
+            }
+        });
+
+        Ok(futures::future::join_all(targets).await)
+    }
+
+    /// Check a subnet of a domain against a server
+    ///
+    /// The geo checks of a server which does not support the client subnet are inconclusive.
+    async fn check_subnet(
+        &self,
+        target: &ResolverTarget,
+        domain: &DomainConfig,
+        geo: &str,
+        subnet: &str,
+        record_type: DnsRecordType,
+    ) -> Vec<IpGeoCheckerTestedData> {
+        let results = self
+            .check_subnet_geo(target, domain, geo, subnet, record_type)
+            .await;
+        if target.ecs_support != Some(EcsSupport::Unsupported) {
+            return results;
+        }
+
+        results
             .into_iter()
-            .flatten()
+            .map(|mut data| {
+                if data.is_ok() || data.is_mismatch() {
+                    data.set_error(Error::EcsUnsupported(target.resolver.clone()));
+                }
+                data
+            })
+            .collect()
+    }
+
+    /// Resolve the host with the subnet and check the Geo IP of every returned IP
+    async fn check_subnet_geo(
+        &self,
+        target: &ResolverTarget,
+        domain: &DomainConfig,
+        geo: &str,
+        subnet: &str,
+        record_type: DnsRecordType,
+    ) -> Vec<IpGeoCheckerTestedData> {
+        let mut data = IpGeoCheckerTestedData::default();
+        data.set_host(&domain.host)
+            .set_resolver(&target.resolver)
+            .set_record_type(record_type)
+            .set_geo(geo)
+            .set_subnet(subnet)
+            .set_expected_any(&domain.expected(geo));
+        if let Some(nameserver) = &target.nameserver {
+            data.set_nameserver(nameserver);
+        }
+
+        let resolver = match &target.client {
+            Ok(resolver) => resolver,
+            Err(e) => return vec![data.set_error(e.clone()).clone()],
+        };
+        let answer = match resolver
+            .resolve_detailed(&domain.host, subnet, record_type)
+            .await
+        {
+            Ok(answer) => answer,
+            Err(failure) => {
+                data.set_attempts(failure.attempts)
+                    .set_latency(failure.latency);
+                return vec![Self::failed_query(data, domain, geo, failure.error)];
+            }
+        };
+        data.set_answer(&answer);
+        if answer.ips.is_empty() {
+            let e = Error::NoData(domain.host.clone());
+            return vec![Self::failed_query(data, domain, geo, e)];
+        }
+
+        // A server which did not echo the option did not use the subnet, like a scope of /0
+        let scope = answer.ecs.map_or(0, |ecs| ecs.scope_prefix);
+        let scope_error = domain
+            .min_scope_prefix
+            .filter(|min| scope < *min)
+            .map(|min| Error::ScopeTooBroad {
+                subnet: subnet.to_string(),
+                scope,
+                min,
+            });
+
+        let geoips = match self.client.batch_get_ip_info(&answer.ips).await {
+            Ok(geoips) => geoips,
+            Err(e) => {
+                let err = Error::ProviderLookup(e.to_string());
+                return answer
+                    .ips
+                    .iter()
+                    .map(|ip| {
+                        let mut data = self.ip_data(&data, &answer, domain, *ip);
+                        data.set_error(err.clone()).clone()
+                    })
+                    .collect();
+            }
+        };
+
+        answer
+            .ips
+            .iter()
+            .zip(geoips)
+            .map(|(ip, geoip)| {
+                let mut data = self.ip_data(&data, &answer, domain, *ip);
+                let Some(country_code) = geoip.country_code.clone().filter(|c| !c.is_empty())
+                else {
+                    let reason = geoip
+                        .message
+                        .clone()
+                        .unwrap_or("no country in the response".to_string());
+                    data.error.get_or_insert(Error::MissingGeoData {
+                        ip: ip.to_string(),
+                        reason,
+                    });
+                    return data.set_geoip(geoip).clone();
+                };
+                // A geo mismatch takes precedence over the scope of the answer
+                let mut data = data.set_actual(&country_code).set_geoip(geoip).test();
+                if let Some(e) = &scope_error {
+                    data.error.get_or_insert(e.clone());
+                }
+                data
+            })
             .collect()
     }
+
+    /// Record a query which got no addresses on the tested data
+    ///
+    /// NXDOMAIN and NODATA are the actual outcome of the test case, which passes if it is the
+    /// expected outcome of the geo. Other failures and unexpected outcomes are errors.
+    fn failed_query(
+        mut data: IpGeoCheckerTestedData,
+        domain: &DomainConfig,
+        geo: &str,
+        error: Error,
+    ) -> IpGeoCheckerTestedData {
+        if let Some(rcode) = error.rcode() {
+            data.set_rcode(rcode);
+        }
+        match DnsOutcome::from_error(&error) {
+            Some(outcome) if domain.outcomes.contains_key(geo) => {
+                data.set_actual(&outcome.to_string()).test()
+            }
+            Some(outcome) => data
+                .set_actual(&outcome.to_string())
+                .set_error(error)
+                .clone(),
+            None => data.set_error(error).clone(),
+        }
+    }
+
+    /// Create the tested data of an IP of the answer, checking the TTL of its record
+    fn ip_data(
+        &self,
+        data: &IpGeoCheckerTestedData,
+        answer: &DnsAnswer,
+        domain: &DomainConfig,
+        ip: IpAddr,
+    ) -> IpGeoCheckerTestedData {
+        let mut data = data.clone();
+        data.set_ip(ip);
+        let Some(ttl) = answer.ttl(ip) else {
+            return data;
+        };
+        data.set_ttl(ttl);
+        if let Some(max) = domain.max_ttl.filter(|max| ttl > *max) {
+            data.error.get_or_insert(Error::TtlTooLong {
+                ip: ip.to_string(),
+                ttl,
+                max,
+            });
+        }
+        data
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::configs_parser::{GeoRoutingConfig, ResolverConfig, RoutingCountryConfig};
+    use crate::dns_client::tests::{
+        address_answer, response_with, spawn_lossy_udp_server, spawn_udp_server,
+    };
+    use hickory_proto::op::ResponseCode;
+    use std::collections::HashMap;
     use std::net::IpAddr;
 
+    /// A provider locating every IP in the US
+    #[derive(Clone)]
+    struct FakeProvider;
+
+    impl GetGeoIpInfo for FakeProvider {
+        #[allow(refining_impl_trait)]
+        async fn get_geoip_info(&self, ip: IpAddr) -> Result<GeoIpResponse, std::fmt::Error> {
+            Ok(GeoIpResponse {
+                query: ip.to_string(),
+                country_code: Some("US".to_string()),
+                ..Default::default()
+            })
+        }
+
+        #[allow(refining_impl_trait)]
+        async fn batch_get_ip_info(
+            &self,
+            ips: &Vec<IpAddr>,
+        ) -> Result<Vec<GeoIpResponse>, std::fmt::Error> {
+            let mut results = vec![];
+            for ip in ips {
+                results.push(self.get_geoip_info(*ip).await?);
+            }
+            Ok(results)
+        }
+    }
+
+    fn fake_checker(dns_resolver: DnsResolver, config: Config) -> IpGeoChecker<FakeProvider> {
+        IpGeoChecker {
+            client: IpGeoProvider(FakeProvider),
+            dns_resolvers: vec![dns_resolver],
+            config,
+        }
+    }
+
+    fn test_config(geo_routing: &[&str]) -> Config {
+        Config {
+            test_subnets: HashMap::from([
+                (
+                    "us".to_string(),
+                    RoutingCountryConfig {
+                        subnets: vec!["44.208.193.0/24".to_string()],
+                    },
+                ),
+                (
+                    "sg".to_string(),
+                    RoutingCountryConfig {
+                        subnets: vec!["175.41.192.0/18".to_string()],
+                    },
+                ),
+            ]),
+            domain: vec![DomainConfig {
+                host: "www.example.com".to_string(),
+                geo_routing: geo_routing.iter().map(|g| (*g).into()).collect(),
+                record_types: vec![DnsRecordType::A],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check() {
+        let addr = spawn_udp_server(address_answer).await;
+        let checker = fake_checker(DnsResolver::Custom(addr), test_config(&["us", "sg", "xx"]));
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 3);
+        let unknown = results.iter().find(|r| r.expected == ["xx"]).unwrap();
+        assert_eq!(unknown.err(), Some(Error::UnknownGeo("xx".to_string())));
+        let us = results.iter().find(|r| r.expected == ["us"]).unwrap();
+        assert!(us.is_ok());
+        assert_eq!(us.ip, Some("192.0.2.1".parse::<IpAddr>().unwrap()));
+        assert_eq!(us.transport, Some(DnsTransport::Udp));
+        assert_eq!(us.attempts, 1);
+        assert!(us.latency.is_some());
+        assert_eq!(unknown.transport, None);
+        let sg = results.iter().find(|r| r.expected == ["sg"]).unwrap();
+        assert!(sg.is_mismatch());
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_timeout() {
+        let addr = spawn_lossy_udp_server(usize::MAX, address_answer).await;
+        let mut config = test_config(&["us"]);
+        config.dns_query = DnsQueryOptions {
+            timeout: Duration::from_millis(100),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        };
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].err(),
+            Some(Error::DnsTimeout("www.example.com".to_string()))
+        );
+        assert_eq!(results[0].attempts, 3);
+        assert!(results[0].latency >= Some(Duration::from_millis(330)));
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_max_concurrent_queries() {
+        let addr = spawn_lossy_udp_server(usize::MAX, address_answer).await;
+        let mut config = test_config(&["us", "sg"]);
+        config.test_subnets.get_mut("us").unwrap().subnets = vec![
+            "44.208.193.0/24".to_string(),
+            "3.80.0.0/12".to_string(),
+            "54.144.0.0/12".to_string(),
+        ];
+        config.dns_query = DnsQueryOptions {
+            timeout: Duration::from_millis(100),
+            retries: 0,
+            backoff: Duration::from_millis(10),
+        };
+        config.max_concurrent_queries = Some(1);
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let start = std::time::Instant::now();
+        let results = checker.check().await;
+
+        // The 4 queries time out one after the other
+        assert!(start.elapsed() >= Duration::from_millis(400));
+        let subnets: Vec<&str> = results.iter().map(|r| r.subnet.as_str()).collect();
+        assert_eq!(
+            subnets,
+            [
+                "44.208.193.0/24",
+                "3.80.0.0/12",
+                "54.144.0.0/12",
+                "175.41.192.0/18"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_authoritative() {
+        let healthy = spawn_udp_server(address_answer).await;
+        let broken = spawn_udp_server(|request| {
+            let mut response = response_with(request, vec![]);
+            response.set_response_code(ResponseCode::ServFail);
+            response
+        })
+        .await;
+        let mut config = test_config(&["us"]);
+        config.domain[0].nameservers = vec![healthy.to_string(), broken.to_string()];
+        let checker = fake_checker(DnsResolver::Custom(healthy), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 2);
+        let ok = results
+            .iter()
+            .find(|r| r.nameserver == Some(healthy.to_string()))
+            .unwrap();
+        assert!(ok.is_ok());
+        assert_eq!(ok.resolver, healthy.to_string());
+        let failed = results
+            .iter()
+            .find(|r| r.nameserver == Some(broken.to_string()))
+            .unwrap();
+        assert_eq!(
+            failed.err(),
+            Some(Error::ServFail("www.example.com".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_min_scope_prefix() {
+        let addr = spawn_udp_server(address_answer).await;
+        let mut config = test_config(&["us"]);
+        config.domain[0].min_scope_prefix = Some(24);
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].scope_prefix, None);
+        assert_eq!(
+            results[0].err(),
+            Some(Error::ScopeTooBroad {
+                subnet: "44.208.193.0/24".to_string(),
+                scope: 0,
+                min: 24,
+            })
+        );
+        assert!(results[0].is_mismatch());
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_min_scope_prefix_geo_mismatch() {
+        let addr = spawn_udp_server(address_answer).await;
+        let mut config = test_config(&["sg"]);
+        config.domain[0].min_scope_prefix = Some(24);
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].err(),
+            Some(Error::GeoMismatch {
+                expected: vec!["sg".to_string()],
+                actual: "us".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_expected_countries() {
+        let addr = spawn_udp_server(address_answer).await;
+        let mut config = test_config(&[]);
+        config.domain[0].geo_routing = vec![
+            GeoRoutingConfig::Expect {
+                region: "sg".to_string(),
+                expect: vec!["JP".to_string(), "US".to_string()],
+            },
+            GeoRoutingConfig::Expect {
+                region: "us".to_string(),
+                expect: vec!["sg".to_string(), "jp".to_string()],
+            },
+        ];
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 2);
+        let sg = results.iter().find(|r| r.geo == "sg").unwrap();
+        assert!(sg.is_ok());
+        assert_eq!(sg.expected, ["jp", "us"]);
+        assert_eq!(sg.actual, "us");
+        let us = results.iter().find(|r| r.geo == "us").unwrap();
+        assert_eq!(
+            us.err(),
+            Some(Error::GeoMismatch {
+                expected: vec!["sg".to_string(), "jp".to_string()],
+                actual: "us".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_outcomes() {
+        let addr = spawn_udp_server(|request| {
+            let name = request.queries()[0].name().to_string();
+            if name.starts_with("blocked") {
+                let mut response = response_with(request, vec![]);
+                response.set_response_code(ResponseCode::NXDomain);
+                response
+            } else if name.starts_with("empty") {
+                response_with(request, vec![])
+            } else {
+                address_answer(request)
+            }
+        })
+        .await;
+        let mut config = test_config(&["us"]);
+        let domain = |host: &str, outcome: Option<DnsOutcome>| DomainConfig {
+            host: host.to_string(),
+            outcomes: outcome
+                .map(|outcome| HashMap::from([("us".to_string(), outcome)]))
+                .unwrap_or_default(),
+            ..config.domain[0].clone()
+        };
+        config.domain = vec![
+            domain("blocked.example.com", Some(DnsOutcome::NxDomain)),
+            domain("empty.example.com", Some(DnsOutcome::NxDomain)),
+            domain("empty.example.org", None),
+            domain("www.example.com", Some(DnsOutcome::NoData)),
+        ];
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+        let result = |host: &str| results.iter().find(|r| r.host == host).unwrap();
+
+        assert_eq!(results.len(), 4);
+        let blocked = result("blocked.example.com");
+        assert!(blocked.is_ok());
+        assert_eq!(blocked.expected, ["nxdomain"]);
+        assert_eq!(blocked.actual, "nxdomain");
+        assert_eq!(blocked.rcode.as_deref(), Some("NXDOMAIN"));
+        assert_eq!(
+            result("empty.example.com").err(),
+            Some(Error::GeoMismatch {
+                expected: vec!["nxdomain".to_string()],
+                actual: "nodata".to_string(),
+            })
+        );
+        let empty = result("empty.example.org");
+        assert!(empty.is_error());
+        assert_eq!(
+            empty.err(),
+            Some(Error::NoData("empty.example.org".to_string()))
+        );
+        assert_eq!(empty.rcode.as_deref(), Some("NOERROR"));
+        assert_eq!(
+            result("www.example.com").err(),
+            Some(Error::GeoMismatch {
+                expected: vec!["nodata".to_string()],
+                actual: "us".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_max_ttl() {
+        let addr = spawn_udp_server(address_answer).await;
+        let mut config = test_config(&["us"]);
+        let checker = fake_checker(DnsResolver::Custom(addr), config.clone());
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_ok());
+        assert_eq!(results[0].ttl, Some(60));
+        assert_eq!(results[0].rcode.as_deref(), Some("NOERROR"));
+        assert_eq!(results[0].server, Some(addr));
+        assert!(results[0].response_time.is_some());
+
+        config.domain[0].max_ttl = Some(30);
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+
+        assert_eq!(
+            results[0].err(),
+            Some(Error::TtlTooLong {
+                ip: "192.0.2.1".to_string(),
+                ttl: 60,
+                max: 30,
+            })
+        );
+        assert!(results[0].is_mismatch());
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_probe_ecs() {
+        let addr = spawn_udp_server(address_answer).await;
+        let mut config = test_config(&["us", "sg"]);
+        config.probe_ecs = true;
+        let checker = fake_checker(DnsResolver::Custom(addr), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_inconclusive()));
+        assert_eq!(
+            results[0].err(),
+            Some(Error::EcsUnsupported(addr.to_string()))
+        );
+        let summary = IpGeoCheckerSummary::from_results(&results);
+        assert_eq!(summary.inconclusive, 2);
+        assert_eq!(summary.mismatched, 0);
+        assert_eq!(summary.errors, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_multiple_resolvers() {
+        let first = spawn_udp_server(address_answer).await;
+        let second = spawn_udp_server(address_answer).await;
+        let checker = IpGeoChecker {
+            client: IpGeoProvider(FakeProvider),
+            dns_resolvers: vec![DnsResolver::Custom(first), DnsResolver::Custom(second)],
+            config: test_config(&["us", "xx"]),
+        };
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 4);
+        let summaries = IpGeoCheckerSummary::by_resolver(&results);
+        assert_eq!(summaries.len(), 2);
+        for (summary, addr) in summaries.iter().zip([first, second]) {
+            assert_eq!(summary.resolver, addr.to_string());
+            assert_eq!(summary.summary.matched, 1);
+            assert_eq!(summary.summary.errors, 1);
+        }
+        assert!(IpGeoCheckerDivergence::from_results(&results).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ip_geo_checker_check_named_resolvers() {
+        let default = spawn_udp_server(address_answer).await;
+        let corp = spawn_udp_server(address_answer).await;
+        let mut config = test_config(&["us"]);
+        config.named_resolvers = HashMap::from([(
+            "corp".to_string(),
+            ResolverConfig {
+                address: corp.to_string(),
+                ..Default::default()
+            },
+        )]);
+        let public = config.domain[0].clone();
+        config.domain.push(DomainConfig {
+            host: "intranet.example.com".to_string(),
+            resolvers: vec!["corp".to_string()],
+            ..public.clone()
+        });
+        config.domain.push(DomainConfig {
+            host: "www.example.org".to_string(),
+            resolvers: vec!["missing".to_string()],
+            ..public
+        });
+        let checker = fake_checker(DnsResolver::Custom(default), config);
+        let results = checker.check().await;
+
+        assert_eq!(results.len(), 3);
+        let public = results
+            .iter()
+            .find(|r| r.host == "www.example.com")
+            .unwrap();
+        assert_eq!(public.resolver, default.to_string());
+        assert!(public.is_ok());
+        let intranet = results
+            .iter()
+            .find(|r| r.host == "intranet.example.com")
+            .unwrap();
+        assert_eq!(intranet.resolver, "corp");
+        assert!(intranet.is_ok());
+        let missing = results
+            .iter()
+            .find(|r| r.host == "www.example.org")
+            .unwrap();
+        assert_eq!(missing.resolver, "missing");
+        assert_eq!(
+            missing.err(),
+            Some(Error::UnknownResolver("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ip_geo_checker_divergence() {
+        let answer = |resolver: &str, ip: &str, actual: &str| {
+            IpGeoCheckerTestedData::default()
+                .set_host("www.example.com")
+                .set_resolver(resolver)
+                .set_geo("us")
+                .set_subnet("44.208.193.0/24")
+                .set_ip(ip.parse().unwrap())
+                .set_expected("us")
+                .set_actual(actual)
+                .test()
+        };
+        let results = vec![
+            answer("google", "192.0.2.1", "US"),
+            answer("google", "192.0.2.2", "US"),
+            answer("cloudflare", "192.0.2.3", "US"),
+            answer("quad9", "198.51.100.1", "SG"),
+            IpGeoCheckerTestedData::default()
+                .set_host("www.example.com")
+                .set_resolver("isp")
+                .set_geo("us")
+                .set_subnet("44.208.193.0/24")
+                .set_error(Error::ServFail("www.example.com".to_string()))
+                .clone(),
+        ];
+
+        let divergences = IpGeoCheckerDivergence::from_results(&results);
+        assert_eq!(divergences.len(), 1);
+        let resolvers: Vec<&str> = divergences[0]
+            .answers
+            .iter()
+            .map(|a| a.resolver.as_str())
+            .collect();
+        assert_eq!(resolvers, vec!["google", "cloudflare", "quad9"]);
+        assert_eq!(divergences[0].answers[0].ips.len(), 2);
+        assert_eq!(divergences[0].answers[2].countries, vec!["sg".to_string()]);
+
+        let results = &results[..3];
+        assert!(IpGeoCheckerDivergence::from_results(results).is_empty());
+    }
+
+    #[test]
+    fn test_ip_geo_checker_summary() {
+        let mut matched = IpGeoCheckerTestedData::default();
+        matched.set_expected("US").set_actual("US");
+        let mut mismatched = IpGeoCheckerTestedData::default();
+        mismatched.set_expected("US").set_actual("CA");
+        let mut errored = IpGeoCheckerTestedData::default();
+        errored.set_error(Error::UnknownGeo("xx".to_string()));
+
+        let summary =
+            IpGeoCheckerSummary::from_results(&[matched.test(), mismatched.test(), errored.test()]);
+        assert_eq!(
+            summary,
+            IpGeoCheckerSummary {
+                total: 3,
+                matched: 1,
+                mismatched: 1,
+                inconclusive: 0,
+                errors: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_ip_geo_checker_tested_data_serialize() {
+        let mut data = IpGeoCheckerTestedData::default();
+        data.set_host("example.com")
+            .set_resolver(DnsResolver::Google)
+            .set_expected("US")
+            .set_error(Error::NxDomain("example.com".to_string()));
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["host"], "example.com");
+        assert_eq!(json["resolver"], "google");
+        assert_eq!(json["record_type"], "A");
+        assert_eq!(json["ip"], serde_json::Value::Null);
+        assert_eq!(json["transport"], serde_json::Value::Null);
+        assert_eq!(json["attempts"], 0);
+        assert_eq!(json["latency_ms"], serde_json::Value::Null);
+        assert_eq!(json["ttl"], serde_json::Value::Null);
+        assert_eq!(json["error"]["kind"], "nx_domain");
+        assert_eq!(
+            json["error"]["message"],
+            "DNS query for example.com returned NXDOMAIN"
+        );
+
+        data.set_ip("192.0.2.1".parse().unwrap())
+            .set_transport(DnsTransport::Tcp)
+            .set_attempts(2)
+            .set_latency(Duration::from_micros(1500))
+            .set_ttl(60);
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["ip"], "192.0.2.1");
+        assert_eq!(json["transport"], "tcp");
+        assert_eq!(json["attempts"], 2);
+        assert_eq!(json["latency_ms"], 1.5);
+        assert_eq!(json["ttl"], 60);
+        assert!(json["geoip"].get("country_code").is_some());
+
+        // A sinkholed answer is kept
+        data.set_ip("0.0.0.0".parse().unwrap());
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["ip"], "0.0.0.0");
+    }
+
+    #[test]
+    fn test_geo_ip_response_deserialize_failure() {
+        let res: GeoIpResponse = serde_json::from_str(
+            r#"{"status":"fail","message":"reserved range","query":"10.0.0.1"}"#,
+        )
+        .unwrap();
+        assert_eq!(res.query, "10.0.0.1");
+        assert_eq!(res.country_code, None);
+        assert_eq!(res.message, Some("reserved range".to_string()));
+    }
+
     #[test]
     fn test_ip_geo_checker_tested_data_set_host() {
         let mut data = IpGeoCheckerTestedData::default();
@@ -258,7 +1500,14 @@ mod tests {
         let mut data = IpGeoCheckerTestedData::default();
         let ip: IpAddr = "192.0.2.1".parse().unwrap();
         data.set_ip(ip);
-        assert_eq!(data.ip, ip);
+        assert_eq!(data.ip, Some(ip));
+    }
+
+    #[test]
+    fn test_ip_geo_checker_tested_data_set_cname_chain() {
+        let mut data = IpGeoCheckerTestedData::default();
+        data.set_cname_chain(&["edge.example.net".to_string()]);
+        assert_eq!(data.cname_chain, vec![String::from("edge.example.net")]);
     }
 
     #[test]
@@ -280,7 +1529,7 @@ mod tests {
     fn test_ip_geo_checker_tested_data_set_expected() {
         let mut data = IpGeoCheckerTestedData::default();
         data.set_expected("US");
-        assert_eq!(data.expected, String::from("us"));
+        assert_eq!(data.expected, vec![String::from("us")]);
     }
 
     #[test]
@@ -302,5 +1551,19 @@ mod tests {
         let mut data = IpGeoCheckerTestedData::default();
         data.set_expected("US").set_actual("CA");
         assert!(data.test().is_err());
+        assert!(data.test().is_mismatch());
+    }
+
+    #[test]
+    fn test_ip_geo_checker_tested_data_test_keeps_error() {
+        let mut data = IpGeoCheckerTestedData::default();
+        data.set_expected("US")
+            .set_error(Error::ServFail("example.com".to_string()));
+        let tested = data.test();
+        assert_eq!(
+            tested.err(),
+            Some(Error::ServFail("example.com".to_string()))
+        );
+        assert!(!tested.is_mismatch());
     }
 }
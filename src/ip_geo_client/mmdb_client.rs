@@ -1,6 +1,8 @@
 use std::{env, net::IpAddr, sync::Arc};
 
-use crate::{configs_parser::Config, ip_geo_checker::GeoIpResponse};
+use maxminddb::{geoip2, MaxMindDBError};
+
+use crate::{configs_parser::Config, error::Error, ip_geo_checker::GeoIpResponse};
 
 use super::{GetGeoIpInfo, IpGeoProviderType, NewProvider};
 
@@ -10,105 +12,208 @@ pub struct MMDBClient {
 }
 
 impl NewProvider for MMDBClient {
+    /// # Panics
+    ///
+    /// Panics if the database cannot be opened, see [`MMDBClient::try_new`].
     fn new(config: &Config) -> Self {
-        let mmdb_path = config
+        Self::try_new(config).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn get_provider_type(&self) -> String {
+        IpGeoProviderType::MMDB.to_string()
+    }
+}
+
+impl MMDBClient {
+    /// Open the database at the path of the config
+    ///
+    /// Fails if the database cannot be read or is not a valid MMDB file.
+    pub fn try_new(config: &Config) -> Result<Self, Error> {
+        let mmdb_path = Self::mmdb_path(config);
+        let reader =
+            maxminddb::Reader::open_readfile(&mmdb_path).map_err(|e| Error::ConfigRead {
+                path: mmdb_path,
+                message: e.to_string(),
+            })?;
+
+        Ok(Self {
+            reader: Arc::new(reader),
+        })
+    }
+
+    /// Get the path of the database, `mmdb_path` in the config, the `MMDB_PATH` environment
+    /// variable or `./mmdb/GeoLite2-City.mmdb`
+    pub fn mmdb_path(config: &Config) -> String {
+        Self::configured_mmdb_path(config).unwrap_or("./mmdb/GeoLite2-City.mmdb".to_string())
+    }
+
+    /// Get the path of the database set by `mmdb_path` in the config or the `MMDB_PATH`
+    /// environment variable, if any
+    pub fn configured_mmdb_path(config: &Config) -> Option<String> {
+        config
             .mmdb_path
             .clone()
-            .unwrap_or(env::var("MMDB_PATH").unwrap_or("./mmdb/GeoLite2-City.mmdb".to_string()));
-        let reader = maxminddb::Reader::open_readfile(mmdb_path).unwrap();
-        Self {
-            reader: Arc::new(reader),
+            .or_else(|| env::var("MMDB_PATH").ok())
+    }
+
+    /// Look up an IP in the database
+    ///
+    /// An IP missing from the database is not an error, it results in a response without geo
+    /// data (see [`GeoIpResponse::not_found`]).
+    fn lookup(&self, ip: IpAddr) -> Result<GeoIpResponse, MaxMindDBError> {
+        match self.reader.lookup::<geoip2::City>(ip) {
+            Ok(record) => Ok(city_to_geoip_response(ip, &record)),
+            Err(MaxMindDBError::AddressNotFoundError(_)) => Ok(GeoIpResponse::not_found(ip)),
+            Err(e) => Err(e),
         }
     }
+}
 
-    fn get_provider_type(&self) -> String {
-        IpGeoProviderType::MMDB.to_string()
+/// Map a city record to a response, tolerating any missing field
+///
+/// IPs which are only known at country level, e.g. anycast and cloud IPs, have no city,
+/// subdivision or location data. When the country itself is missing, the registered country is
+/// used instead.
+fn city_to_geoip_response(ip: IpAddr, record: &geoip2::City) -> GeoIpResponse {
+    let country = record
+        .country
+        .as_ref()
+        .filter(|c| c.iso_code.is_some())
+        .or(record.registered_country.as_ref());
+    let subdivision = record.subdivisions.as_ref().and_then(|s| s.first());
+
+    GeoIpResponse {
+        query: ip.to_string(),
+        country: country.and_then(|c| english_name(&c.names)),
+        country_code: country.and_then(|c| c.iso_code).map(|c| c.to_string()),
+        region: subdivision.and_then(|s| s.iso_code).map(|s| s.to_string()),
+        region_name: subdivision.and_then(|s| english_name(&s.names)),
+        city: record.city.as_ref().and_then(|c| english_name(&c.names)),
+        lat: record.location.as_ref().and_then(|l| l.latitude),
+        lon: record.location.as_ref().and_then(|l| l.longitude),
+        message: None,
     }
 }
 
+/// Get the English name out of the localized names of a record
+fn english_name(names: &Option<std::collections::BTreeMap<&str, &str>>) -> Option<String> {
+    names
+        .as_ref()
+        .and_then(|n| n.get("en"))
+        .map(|n| n.to_string())
+}
+
 impl GetGeoIpInfo for MMDBClient {
     #[allow(refining_impl_trait)]
-    async fn get_geoip_info(&self, ip: IpAddr) -> Result<GeoIpResponse, maxminddb::MaxMindDBError> {
-        let ip = ip.to_string();
-        let record: maxminddb::geoip2::City = self.reader.lookup(ip.parse().unwrap())?;
-        Ok(GeoIpResponse {
-            query: ip,
-            country: record
-                .country
-                .clone()
-                .unwrap()
-                .iso_code
-                .unwrap()
-                .to_string(),
-            country_code: record
-                .country
-                .clone()
-                .unwrap()
-                .iso_code
-                .unwrap()
-                .to_string(),
-            region: record
-                .subdivisions
-                .clone()
-                .unwrap()
-                .get(0)
-                .unwrap()
-                .iso_code
-                .unwrap()
-                .to_string(),
-            region_name: "".to_string(),
-            city: record
-                .city
-                .unwrap()
-                .names
-                .unwrap()
-                .get("en")
-                .unwrap()
-                .to_string(),
-            lat: record.location.clone().unwrap().latitude.unwrap(),
-            lon: record.location.clone().unwrap().longitude.unwrap(),
-        })
+    async fn get_geoip_info(&self, ip: IpAddr) -> Result<GeoIpResponse, MaxMindDBError> {
+        self.lookup(ip)
     }
 
     #[allow(refining_impl_trait)]
     async fn batch_get_ip_info(
         &self,
         ips: &Vec<IpAddr>,
-    ) -> Result<Vec<GeoIpResponse>, maxminddb::MaxMindDBError> {
-        let ips = ips.iter().map(|a| a.to_string()).collect::<Vec<String>>();
-        let mut results = vec![];
-        for ip in ips.iter() {
-            let record: maxminddb::geoip2::City = self.reader.lookup(ip.parse().unwrap())?;
-            results.push(GeoIpResponse {
-                query: ip.to_string(),
-                country: record
-                    .country
-                    .clone()
-                    .unwrap()
-                    .iso_code
-                    .unwrap()
-                    .to_string(),
-                country_code: record
-                    .country
-                    .clone()
-                    .unwrap()
-                    .iso_code
-                    .unwrap()
-                    .to_string(),
-                region: "".to_string(),
-                region_name: "".to_string(),
-                city: record
-                    .city
-                    .unwrap()
-                    .names
-                    .unwrap()
-                    .get("en")
-                    .unwrap()
-                    .to_string(),
-                lat: record.location.clone().unwrap().latitude.unwrap(),
-                lon: record.location.clone().unwrap().longitude.unwrap(),
-            });
+    ) -> Result<Vec<GeoIpResponse>, MaxMindDBError> {
+        ips.iter().map(|ip| self.lookup(*ip)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maxminddb::geoip2::city;
+    use std::collections::BTreeMap;
+
+    fn country(iso_code: &'static str, name: &'static str) -> city::Country<'static> {
+        city::Country {
+            geoname_id: None,
+            is_in_european_union: None,
+            iso_code: Some(iso_code),
+            names: Some(BTreeMap::from([("en", name)])),
+        }
+    }
+
+    fn empty_city() -> geoip2::City<'static> {
+        geoip2::City {
+            city: None,
+            continent: None,
+            country: None,
+            location: None,
+            postal: None,
+            registered_country: None,
+            represented_country: None,
+            subdivisions: None,
+            traits: None,
         }
-        Ok(results)
+    }
+
+    #[test]
+    fn test_try_new_missing_database() {
+        let config = Config {
+            mmdb_path: Some("./missing/GeoLite2-City.mmdb".to_string()),
+            ..Default::default()
+        };
+        let result = MMDBClient::try_new(&config);
+        assert!(matches!(
+            result,
+            Err(Error::ConfigRead { path, .. }) if path == "./missing/GeoLite2-City.mmdb"
+        ));
+    }
+
+    #[test]
+    fn test_city_to_geoip_response_full() {
+        let record = geoip2::City {
+            city: Some(city::City {
+                geoname_id: None,
+                names: Some(BTreeMap::from([("en", "Singapore")])),
+            }),
+            country: Some(country("SG", "Singapore")),
+            location: Some(city::Location {
+                accuracy_radius: None,
+                latitude: Some(1.29),
+                longitude: Some(103.85),
+                metro_code: None,
+                time_zone: None,
+            }),
+            subdivisions: Some(vec![city::Subdivision {
+                geoname_id: None,
+                iso_code: Some("01"),
+                names: Some(BTreeMap::from([("en", "Central Singapore")])),
+            }]),
+            ..empty_city()
+        };
+        let res = city_to_geoip_response("175.41.192.1".parse().unwrap(), &record);
+        assert_eq!(res.query, "175.41.192.1");
+        assert_eq!(res.country_code, Some("SG".to_string()));
+        assert_eq!(res.region, Some("01".to_string()));
+        assert_eq!(res.region_name, Some("Central Singapore".to_string()));
+        assert_eq!(res.city, Some("Singapore".to_string()));
+        assert_eq!(res.lat, Some(1.29));
+        assert_eq!(res.lon, Some(103.85));
+    }
+
+    #[test]
+    fn test_city_to_geoip_response_country_only() {
+        let record = geoip2::City {
+            country: Some(country("US", "United States")),
+            ..empty_city()
+        };
+        let res = city_to_geoip_response("1.1.1.1".parse().unwrap(), &record);
+        assert_eq!(res.country, Some("United States".to_string()));
+        assert_eq!(res.country_code, Some("US".to_string()));
+        assert_eq!(res.region, None);
+        assert_eq!(res.city, None);
+        assert_eq!(res.lat, None);
+    }
+
+    #[test]
+    fn test_city_to_geoip_response_registered_country_fallback() {
+        let record = geoip2::City {
+            registered_country: Some(country("JP", "Japan")),
+            ..empty_city()
+        };
+        let res = city_to_geoip_response("1.0.16.1".parse().unwrap(), &record);
+        assert_eq!(res.country_code, Some("JP".to_string()));
+        assert_eq!(res.country, Some("Japan".to_string()));
     }
 }
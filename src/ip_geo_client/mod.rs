@@ -1,6 +1,6 @@
 use std::{error::Error, net::IpAddr};
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 use crate::{configs_parser::Config, ip_geo_checker::GeoIpResponse};
 
@@ -25,6 +25,7 @@ pub trait GetGeoIpInfo {
     ) -> impl std::future::Future<Output = Result<GeoIpResponse, impl Error>> + Send;
 
     /// Get the geoip info for a batch of IPs
+    #[allow(clippy::ptr_arg)]
     fn batch_get_ip_info(
         &self,
         ips: &Vec<IpAddr>,
@@ -40,23 +41,18 @@ pub trait GetGeoIpInfo {
 /// * `IpApi` - The IP API provider
 /// * `MMDB` - The MMDB provider
 /// * `None` - No provider
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default)]
 pub enum IpGeoProviderType {
     #[cfg(feature = "ip-api")]
-    #[serde(alias = "ip-api")]
+    #[cfg_attr(not(feature = "mmdb"), default)]
     IpApi,
     #[cfg(feature = "mmdb")]
-    #[serde(alias = "mmdb")]
+    #[default]
     MMDB,
+    #[cfg_attr(not(any(feature = "mmdb", feature = "ip-api")), default)]
     None,
 }
 
-impl Default for IpGeoProviderType {
-    fn default() -> Self {
-        Self::MMDB
-    }
-}
-
 impl std::fmt::Display for IpGeoProviderType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -69,6 +65,33 @@ impl std::fmt::Display for IpGeoProviderType {
     }
 }
 
+impl std::str::FromStr for IpGeoProviderType {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "ip-api")]
+            "ip-api" | "ipapi" => Ok(Self::IpApi),
+            #[cfg(feature = "mmdb")]
+            "mmdb" => Ok(Self::MMDB),
+            "none" => Ok(Self::None),
+            name if ["ip-api", "ipapi", "mmdb"].contains(&name) => {
+                Err(crate::error::Error::ProviderNotCompiledIn(s.to_string()))
+            }
+            _ => Err(crate::error::Error::UnknownProvider(s.to_string())),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for IpGeoProviderType {
+    /// Deserialize a provider from its name, rejecting the providers which are not compiled in
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct IpGeoProvider<T>(pub T);
 
@@ -0,0 +1,272 @@
+use std::io::{self, Write};
+
+use crate::ip_geo_checker::IpGeoCheckerTestedData;
+
+/// Write the tested data of a run as a JUnit XML report
+///
+/// Every domain is a testsuite and every (geo, subnet, record type, IP, server) a testcase.
+/// Geo mismatches are reported as failures, inconclusive cases as skipped and the cases which
+/// could not be checked as errors.
+///
+/// # Examples
+///
+/// ```
+/// use dns_geolocation_checker::ip_geo_checker::IpGeoCheckerTestedData;
+/// use dns_geolocation_checker::junit_report::write_junit_report;
+///
+/// let mut data = IpGeoCheckerTestedData::default();
+/// data.set_host("www.example.com").set_expected("us").set_actual("us");
+///
+/// let mut report = vec![];
+/// write_junit_report(&[data], &mut report).unwrap();
+///
+/// let report = String::from_utf8(report).unwrap();
+/// assert!(report.contains(
+///     r#"<testsuite name="www.example.com" tests="1" failures="0" errors="0" skipped="0">"#
+/// ));
+/// ```
+pub fn write_junit_report<W: Write>(
+    results: &[IpGeoCheckerTestedData],
+    mut writer: W,
+) -> io::Result<()> {
+    // Group the results by domain, keeping the order in which the domains were checked
+    let mut suites: Vec<(&str, Vec<&IpGeoCheckerTestedData>)> = vec![];
+    for result in results {
+        match suites.iter_mut().find(|(host, _)| *host == result.host) {
+            Some((_, cases)) => cases.push(result),
+            None => suites.push((&result.host, vec![result])),
+        }
+    }
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<testsuites name="dns-geo-checker" tests="{}" failures="{}" errors="{}" skipped="{}">"#,
+        results.len(),
+        count_failures(results.iter()),
+        count_errors(results.iter()),
+        count_skipped(results.iter()),
+    )?;
+
+    for (host, cases) in suites.iter() {
+        writeln!(
+            writer,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" skipped="{}">"#,
+            escape(host),
+            cases.len(),
+            count_failures(cases.iter().copied()),
+            count_errors(cases.iter().copied()),
+            count_skipped(cases.iter().copied()),
+        )?;
+
+        for case in cases {
+            write!(
+                writer,
+                r#"    <testcase classname="{}" name="{}""#,
+                escape(host),
+                escape(&testcase_name(case)),
+            )?;
+
+            match &case.error {
+                None => writeln!(writer, "/>")?,
+                Some(error) => {
+                    let tag = if error.is_mismatch() {
+                        "failure"
+                    } else if error.is_inconclusive() {
+                        "skipped"
+                    } else {
+                        "error"
+                    };
+                    writeln!(writer, ">")?;
+                    writeln!(
+                        writer,
+                        r#"      <{} type="{}" message="{}"/>"#,
+                        tag,
+                        error.kind(),
+                        escape(&error.to_string()),
+                    )?;
+                    writeln!(writer, "    </testcase>")?;
+                }
+            }
+        }
+
+        writeln!(writer, "  </testsuite>")?;
+    }
+
+    writeln!(writer, "</testsuites>")
+}
+
+/// Name a testcase after its geo, subnet, record type, IP and server, leaving out the parts it
+/// did not get to
+///
+/// The server is the resolver, or the authoritative nameserver followed by its address, so the
+/// cases of every server of a domain are named apart.
+fn testcase_name(case: &IpGeoCheckerTestedData) -> String {
+    let mut name = vec![case.geo.clone()];
+    if !case.subnet.is_empty() {
+        name.push(case.subnet.clone());
+        name.push(case.record_type.to_string());
+    }
+    if let Some(ip) = case.ip {
+        name.push(ip.to_string());
+    }
+    match (&case.nameserver, case.resolver.is_empty()) {
+        (Some(nameserver), true) => name.push(format!("@{}", nameserver)),
+        (Some(nameserver), false) => name.push(format!("@{} ({})", nameserver, case.resolver)),
+        (None, false) => name.push(format!("@{}", case.resolver)),
+        (None, true) => {}
+    }
+    name.join(" ")
+}
+
+fn count_failures<'a>(cases: impl Iterator<Item = &'a IpGeoCheckerTestedData>) -> usize {
+    cases.filter(|c| c.is_mismatch()).count()
+}
+
+fn count_errors<'a>(cases: impl Iterator<Item = &'a IpGeoCheckerTestedData>) -> usize {
+    cases.filter(|c| c.is_error()).count()
+}
+
+fn count_skipped<'a>(cases: impl Iterator<Item = &'a IpGeoCheckerTestedData>) -> usize {
+    cases.filter(|c| c.is_inconclusive()).count()
+}
+
+/// Escape a string for use in an XML attribute
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_client::DnsRecordType;
+    use crate::error::Error;
+
+    fn tested_data(host: &str, geo: &str, actual: &str) -> IpGeoCheckerTestedData {
+        IpGeoCheckerTestedData::default()
+            .set_host(host)
+            .set_resolver("google")
+            .set_geo(geo)
+            .set_subnet("44.208.193.0/24")
+            .set_ip("192.0.2.1".parse().unwrap())
+            .set_expected(geo)
+            .set_actual(actual)
+            .test()
+    }
+
+    #[test]
+    fn test_write_junit_report() {
+        let results = vec![
+            tested_data("a.example.com", "us", "us"),
+            tested_data("b.example.com", "us", "ca"),
+            tested_data("a.example.com", "sg", "sg"),
+            IpGeoCheckerTestedData::default()
+                .set_host("b.example.com")
+                .set_resolver("google")
+                .set_geo("sg")
+                .set_subnet("175.41.192.0/18")
+                .set_error(Error::ServFail("b.example.com".to_string()))
+                .clone(),
+        ];
+
+        let mut report = vec![];
+        write_junit_report(&results, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        assert!(report.contains(
+            r#"<testsuites name="dns-geo-checker" tests="4" failures="1" errors="1" skipped="0">"#
+        ));
+        assert!(report.contains(
+            r#"<testsuite name="a.example.com" tests="2" failures="0" errors="0" skipped="0">"#
+        ));
+        assert!(report.contains(
+            r#"<testsuite name="b.example.com" tests="2" failures="1" errors="1" skipped="0">"#
+        ));
+        assert!(report.contains(
+            r#"<testcase classname="a.example.com" name="us 44.208.193.0/24 A 192.0.2.1 @google"/>"#
+        ));
+        assert!(
+            report.contains(r#"<failure type="geo_mismatch" message="Expected: us, Actual: ca"/>"#)
+        );
+        assert!(report.contains(
+            r#"<testcase classname="b.example.com" name="sg 175.41.192.0/18 A @google">"#
+        ));
+        assert!(report.contains(
+            r#"<error type="serv_fail" message="DNS query for b.example.com returned SERVFAIL"/>"#
+        ));
+        assert_eq!(report.matches("<testsuite ").count(), 2);
+    }
+
+    #[test]
+    fn test_write_junit_report_resolvers() {
+        let mut results = vec![];
+        for resolver in ["google", "cloudflare"] {
+            for record_type in [DnsRecordType::A, DnsRecordType::AAAA] {
+                results.push(
+                    IpGeoCheckerTestedData::default()
+                        .set_host("a.example.com")
+                        .set_resolver(resolver)
+                        .set_geo("us")
+                        .set_subnet("44.208.193.0/24")
+                        .set_record_type(record_type)
+                        .set_error(Error::ServFail("a.example.com".to_string()))
+                        .clone(),
+                );
+            }
+        }
+        results.push(
+            tested_data("a.example.com", "us", "us")
+                .set_resolver("192.0.2.53:53")
+                .set_nameserver("ns1.example.com")
+                .clone(),
+        );
+
+        let mut report = vec![];
+        write_junit_report(&results, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        let names: Vec<&str> = report
+            .lines()
+            .filter_map(|line| line.split(r#" name=""#).nth(1))
+            .filter_map(|rest| rest.split('"').next())
+            .skip(2)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "us 44.208.193.0/24 A @google",
+                "us 44.208.193.0/24 AAAA @google",
+                "us 44.208.193.0/24 A @cloudflare",
+                "us 44.208.193.0/24 AAAA @cloudflare",
+                "us 44.208.193.0/24 A 192.0.2.1 @ns1.example.com (192.0.2.53:53)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_junit_report_inconclusive() {
+        let mut data = tested_data("a.example.com", "us", "us");
+        data.set_error(Error::EcsUnsupported("google".to_string()));
+
+        let mut report = vec![];
+        write_junit_report(&[data], &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        assert!(report.contains(
+            r#"<testsuites name="dns-geo-checker" tests="1" failures="0" errors="0" skipped="1">"#
+        ));
+        assert!(report.contains(
+            r#"<testsuite name="a.example.com" tests="1" failures="0" errors="0" skipped="1">"#
+        ));
+        assert!(report.contains(r#"<skipped type="ecs_unsupported" message="inconclusive, google does not support the EDNS client subnet option"/>"#));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#"<a & "b">"#), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
@@ -1,4 +1,7 @@
 pub mod configs_parser;
 pub mod dns_client;
+pub mod error;
+pub mod interpolation;
 pub mod ip_geo_checker;
 pub mod ip_geo_client;
+pub mod junit_report;